@@ -0,0 +1,238 @@
+//! 一次上传多个安装包, 同时上传的数量有上限, 所有文件共用一个进度条
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures_util::{stream, StreamExt};
+use serde_json::{json, Value};
+
+use crate::{
+    app::{self, UploadOutcome},
+    client::PgyerClient,
+    error::{PgyerError, Result},
+    options::UploadOptions,
+    output::{self, print_result},
+    progress::UploadProgress,
+    table,
+};
+
+/// 单个文件的上传结果
+#[derive(Debug)]
+pub struct FileResult {
+    pub file: PathBuf,
+    pub outcome: Result<UploadOutcome>,
+}
+
+/// 展开文件参数中的通配符, 如 `build/outputs/**/*.apk`, 没有通配符的路径原样保留
+///
+/// 通配符没有匹配到文件时报错, 同一个文件只保留第一次出现的位置
+pub fn expand_files(patterns: &[&str]) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for pattern in patterns {
+        if !pattern.contains(['*', '?', '[']) {
+            files.push(PathBuf::from(pattern));
+            continue;
+        }
+
+        let paths = glob::glob(pattern).map_err(|err| {
+            PgyerError::Validation(format!("文件匹配模式不正确 {}: {}", pattern, err))
+        })?;
+        let matched: Vec<PathBuf> = paths
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        if matched.is_empty() {
+            return Err(PgyerError::Validation(format!(
+                "没有匹配 {} 的文件",
+                pattern
+            )));
+        }
+        files.extend(matched);
+    }
+
+    let mut seen = HashSet::new();
+    files.retain(|file| seen.insert(file.clone()));
+    Ok(files)
+}
+
+/// 同时最多上传 `parallel` 个文件, 结果按传入的顺序返回
+///
+/// 上传期间不输出每个文件的提示信息, 只显示总进度, 警告和重试日志前加上文件名
+pub async fn upload_all(
+    client: &PgyerClient,
+    files: Vec<UploadOptions>,
+    parallel: usize,
+) -> Vec<FileResult> {
+    let count = files.len();
+    let total_size = files
+        .iter()
+        .filter_map(|options| fs::metadata(&options.file).ok())
+        .map(|metadata| metadata.len())
+        .sum();
+    let progress = Arc::new(UploadProgress::new(total_size));
+    let finished = AtomicUsize::new(0);
+    progress.set_message(&format!("已完成 0/{} 个文件", count));
+
+    output::set_quiet(true);
+    let mut results: Vec<(usize, FileResult)> = stream::iter(files.into_iter().enumerate())
+        .map(|(index, options)| {
+            let progress = &progress;
+            let finished = &finished;
+            async move {
+                let name = options
+                    .file
+                    .file_name()
+                    .unwrap_or(options.file.as_os_str())
+                    .to_string_lossy()
+                    .into_owned();
+                let outcome = output::in_batch(
+                    name,
+                    progress.clone(),
+                    app::upload_with_progress(client, &options, Some(progress)),
+                )
+                .await;
+                let finished = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                progress.set_message(&format!("已完成 {}/{} 个文件", finished, count));
+                let result = FileResult {
+                    file: options.file,
+                    outcome,
+                };
+                (index, result)
+            }
+        })
+        .buffer_unordered(parallel.max(1))
+        .collect()
+        .await;
+    output::set_quiet(false);
+    progress.finish();
+
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// 输出每个文件的结果, 有文件失败时返回错误
+pub fn report(results: &[FileResult]) -> Result<()> {
+    let failed = results
+        .iter()
+        .filter(|result| result.outcome.is_err())
+        .count();
+
+    if output::is_json() {
+        let files: Vec<Value> = results.iter().map(result_json).collect();
+        print_result(&json!({
+            "files": files,
+            "total": results.len(),
+            "failed": failed,
+        }));
+    } else {
+        println!(
+            "{}",
+            table::render(
+                results,
+                &table::upload_result_columns(),
+                table::terminal_width()
+            )
+        );
+        println!(
+            "共 {} 个文件, 成功 {} 个, 失败 {} 个",
+            results.len(),
+            results.len() - failed,
+            failed
+        );
+    }
+
+    if failed > 0 {
+        return Err(PgyerError::Batch {
+            failed,
+            total: results.len(),
+        });
+    }
+    Ok(())
+}
+
+/// 单个文件结果的 JSON, 失败时包含和错误输出相同格式的 error
+pub(crate) fn result_json(result: &FileResult) -> Value {
+    let file = result.file.display().to_string();
+    match &result.outcome {
+        Ok(UploadOutcome::Published(build)) => {
+            json!({ "file": file, "status": "published", "build": build })
+        }
+        Ok(UploadOutcome::Skipped(build)) => {
+            json!({ "file": file, "status": "skipped", "build": build })
+        }
+        Ok(UploadOutcome::Processing { build_key }) => {
+            json!({ "file": file, "status": "processing", "buildKey": build_key })
+        }
+        Err(err) => json!({
+            "file": file,
+            "status": "failed",
+            "error": output::error_document(err, err.exit_code())["error"],
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn touch(dir: &TempDir, name: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, b"data").unwrap();
+        path
+    }
+
+    #[test]
+    fn expand_glob_patterns() {
+        let dir = TempDir::new().unwrap();
+        let a = touch(&dir, "a.apk");
+        let b = touch(&dir, "sub/b.apk");
+        touch(&dir, "c.ipa");
+        // 目录不会被匹配
+        fs::create_dir(dir.path().join("d.apk")).unwrap();
+
+        let pattern = format!("{}/**/*.apk", dir.path().display());
+        assert_eq!(expand_files(&[&pattern]).unwrap(), [a, b]);
+    }
+
+    #[test]
+    fn keep_plain_paths() {
+        // 不是匹配模式的路径原样保留, 由上传时检查是否存在
+        let files = expand_files(&["missing.apk", "dir/app.ipa"]).unwrap();
+        assert_eq!(
+            files,
+            [PathBuf::from("missing.apk"), PathBuf::from("dir/app.ipa")]
+        );
+    }
+
+    #[test]
+    fn reject_unmatched_pattern() {
+        let dir = TempDir::new().unwrap();
+        let pattern = format!("{}/*.apk", dir.path().display());
+        assert!(matches!(
+            expand_files(&[&pattern]),
+            Err(PgyerError::Validation(_))
+        ));
+        assert!(expand_files(&["[.apk"]).is_err());
+    }
+
+    #[test]
+    fn remove_duplicates_keeping_order() {
+        let dir = TempDir::new().unwrap();
+        let b = touch(&dir, "b.apk");
+        let a = touch(&dir, "a.apk");
+        let pattern = format!("{}/*.apk", dir.path().display());
+        let b_path = b.to_str().unwrap();
+        let files = expand_files(&[b_path, &pattern, b_path]).unwrap();
+        assert_eq!(files, [b, a]);
+    }
+}
@@ -0,0 +1,666 @@
+//! 命令行参数的定义, 以及把解析结果转换为库使用的参数
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use glob::Pattern;
+use pgyer_uploader::{
+    batch::expand_files,
+    config::Profile,
+    error::{PgyerError, Result},
+    filter::{AppFilter, Platform},
+    options::{
+        ConfigCommand, DeleteOptions, DuplicatePolicy, InstallType, InstallWindow, UploadOptions,
+    },
+    package::PackageInfo,
+    retry::RetryPolicy,
+    table::{select_columns, Column, SortKey, TableOptions},
+    watch::WatchOptions,
+};
+
+const VERSION_NUMBER: &str = "0.2.3";
+const INSTALL_FOREVER: &str = "2";
+const DEFAULT_PARALLEL: usize = 3;
+
+pub fn get_command_params() -> ArgMatches<'static> {
+    get_app_cli().get_matches()
+}
+
+/// 最内层子命令的参数, 包含全局参数, 使用旧的参数时为顶层参数
+pub fn action_params<'a>(matches: &'a ArgMatches<'a>) -> &'a ArgMatches<'a> {
+    match matches.subcommand() {
+        (_, Some(sub_matches)) => action_params(sub_matches),
+        _ => matches,
+    }
+}
+
+/// 命令行指定的 profile, 没有指定时使用配置文件中的 default_profile
+pub fn profile_name<'a>(matches: &'a ArgMatches<'a>) -> Option<&'a str> {
+    matches.value_of("profile")
+}
+
+pub fn retry_policy(matches: &ArgMatches<'_>) -> Result<RetryPolicy> {
+    let mut policy = RetryPolicy::default();
+    if let Some(retries) = matches.value_of("retries") {
+        let retries: u32 = retries
+            .parse()
+            .map_err(|_| PgyerError::Validation(format!("retries 参数不正确: {}", retries)))?;
+        policy.max_attempts = retries.saturating_add(1);
+    }
+
+    if let Some(delay) = matches.value_of("retryDelay") {
+        let delay: f64 = delay
+            .parse()
+            .ok()
+            .filter(|delay: &f64| delay.is_finite() && *delay >= 0.0)
+            .ok_or_else(|| PgyerError::Validation(format!("retry-delay 参数不正确: {}", delay)))?;
+        policy.base_delay = Duration::from_secs_f64(delay);
+    }
+
+    Ok(policy)
+}
+
+/// 要上传的文件, 展开其中的通配符
+pub fn upload_files(matches: &ArgMatches<'_>) -> Result<Vec<PathBuf>> {
+    let patterns: Vec<&str> = matches.values_of("file").into_iter().flatten().collect();
+    if patterns.is_empty() {
+        return Err(PgyerError::Validation(
+            "请携带文件参数来上传应用, -h 获取更多帮助".to_owned(),
+        ));
+    }
+    expand_files(&patterns)
+}
+
+/// 指定了 --watch 时监控的目录和文件
+pub fn watch_options(matches: &ArgMatches<'_>) -> Result<Option<WatchOptions>> {
+    let dir = match matches.value_of("watch") {
+        Some(dir) => dir,
+        None => return Ok(None),
+    };
+
+    let mut options = WatchOptions::new(dir);
+    options.pattern = matches
+        .value_of("pattern")
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|err| {
+                PgyerError::Validation(format!("pattern 参数不正确 {}: {}", pattern, err))
+            })
+        })
+        .transpose()?;
+    if let Some(settle) = parse_seconds(matches, "settle")? {
+        options.settle = settle;
+    }
+    options.ignore_existing = matches.is_present("ignoreExisting");
+    Ok(Some(options))
+}
+
+/// 批量上传时同时上传的文件数
+pub fn parallel(matches: &ArgMatches<'_>) -> Result<usize> {
+    match matches.value_of("parallel") {
+        Some(value) => value
+            .parse()
+            .ok()
+            .filter(|parallel| *parallel > 0)
+            .ok_or_else(|| PgyerError::Validation(format!("parallel 参数不正确: {}", value))),
+        None => Ok(DEFAULT_PARALLEL),
+    }
+}
+
+/// 上传参数, 命令行没有指定的项使用 profile 中的默认值
+pub fn upload_options(
+    matches: &ArgMatches<'_>,
+    profile: &Profile,
+    file: &Path,
+) -> Result<UploadOptions> {
+    let mut options = UploadOptions::new(file);
+    options.channel = matches
+        .value_of("channel")
+        .or(profile.channel.as_deref())
+        .map(str::to_owned);
+    options.description = match matches.value_of("description") {
+        Some(description) => Some(description.to_owned()),
+        // 模板需要安装包信息时才读取安装包
+        None if profile.description.is_some() => {
+            let info = PackageInfo::read(&options.file).ok();
+            profile.render_description(&options.file, info.as_ref())
+        }
+        None => None,
+    };
+    let password = matches.value_of("password").or(profile.password.as_deref());
+    options.install_type = matches
+        .value_of("installType")
+        .or(profile.install_type.as_deref())
+        .map(|install_type| InstallType::parse(install_type, password))
+        .transpose()?;
+    // 没有指定安装方式时不会发送密码, 避免命令行中的密码被静默忽略
+    if matches.is_present("password")
+        && !matches!(options.install_type, Some(InstallType::Password(_)))
+    {
+        return Err(PgyerError::Validation(
+            "password 参数需要和密码安装方式一起使用, 请同时指定 -t 2 或 -t password".to_owned(),
+        ));
+    }
+    options.install_window = install_window(matches)?;
+
+    options.duplicate = if matches.is_present("failOnDuplicate") {
+        DuplicatePolicy::Fail
+    } else if matches.is_present("checkDuplicate") {
+        DuplicatePolicy::Skip
+    } else {
+        DuplicatePolicy::Ignore
+    };
+    options.no_wait = matches.is_present("noWait");
+    if let Some(interval) = parse_seconds(matches, "pollInterval")? {
+        options.poll_interval = interval;
+    }
+    if let Some(timeout) = parse_seconds(matches, "waitTimeout")? {
+        options.wait_timeout = timeout;
+    }
+    Ok(options)
+}
+
+// -i 2 为长期有效, 指定了 -i 1 或者安装日期时为时间段
+fn install_window(matches: &ArgMatches<'_>) -> Result<InstallWindow> {
+    let start = matches.value_of("installStartDate");
+    let end = matches.value_of("installEndDate");
+    if matches.value_of("installDate") == Some(INSTALL_FOREVER) {
+        if start.is_some() || end.is_some() {
+            return Err(PgyerError::Validation(
+                "installDate=2 时不能指定安装时间".to_owned(),
+            ));
+        }
+        return Ok(InstallWindow::Forever);
+    }
+
+    match end {
+        Some(end) => InstallWindow::parse(start, end),
+        None if matches.is_present("installDate") || start.is_some() => Err(
+            PgyerError::Validation("需要传递安装结束时间参数 installEndDate".to_owned()),
+        ),
+        None => Ok(InstallWindow::Forever),
+    }
+}
+
+fn parse_seconds(matches: &ArgMatches<'_>, name: &str) -> Result<Option<Duration>> {
+    matches
+        .value_of(name)
+        .map(|value| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value > 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| PgyerError::Validation(format!("{} 参数不正确: {}", name, value)))
+        })
+        .transpose()
+}
+
+pub fn delete_options(matches: &ArgMatches<'_>) -> DeleteOptions {
+    DeleteOptions {
+        yes: matches.is_present("yes"),
+        dry_run: matches.is_present("dryRun"),
+    }
+}
+
+/// 不带 --all 时的页码
+pub fn page(matches: &ArgMatches<'_>) -> Result<Option<u32>> {
+    if matches.is_present("all") {
+        return Ok(None);
+    }
+    let page = matches.value_of("page").unwrap_or("1");
+    page.parse()
+        .map(Some)
+        .map_err(|_| PgyerError::Validation(format!("page 参数不正确: {}", page)))
+}
+
+pub fn app_filter(matches: &ArgMatches<'_>) -> Result<AppFilter> {
+    Ok(AppFilter {
+        name: matches.value_of("name").map(str::to_owned),
+        platform: matches.value_of("type").map(Platform::parse).transpose()?,
+        since: matches
+            .value_of("since")
+            .map(AppFilter::parse_date)
+            .transpose()?,
+    })
+}
+
+pub fn table_options<T>(
+    matches: &ArgMatches<'_>,
+    columns: Vec<Column<T>>,
+    default_columns: &str,
+) -> Result<TableOptions<T>> {
+    Ok(TableOptions {
+        columns: select_columns(
+            columns,
+            matches.value_of("columns").unwrap_or(default_columns),
+        )?,
+        sort: matches.value_of("sort").map(SortKey::parse).transpose()?,
+    })
+}
+
+pub fn config_command(matches: &ArgMatches<'_>) -> Option<ConfigCommand> {
+    let (command, matches) = matches.subcommand();
+    let matches = matches?;
+    let value = |name: &str| matches.value_of(name).unwrap_or_default().to_owned();
+    let show_secrets = matches.is_present("showSecrets");
+    let command = match command {
+        "get" => ConfigCommand::Get {
+            key: value("key"),
+            show_secrets,
+        },
+        "set" => ConfigCommand::Set {
+            key: value("key"),
+            value: value("value"),
+        },
+        "unset" => ConfigCommand::Unset { key: value("key") },
+        "list" => ConfigCommand::List { show_secrets },
+        "path" => ConfigCommand::Path,
+        "profiles" => ConfigCommand::Profiles,
+        "use" => ConfigCommand::Use {
+            name: value("name"),
+        },
+        _ => return None,
+    };
+    Some(command)
+}
+
+fn get_app_cli() -> App<'static, 'static> {
+    let app = App::new("PGYER APP MANAGER")
+        .version(VERSION_NUMBER)
+        .author("PANG")
+        .about("PGYER APP MANAGER")
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("config profile to use, default is default_profile in the config file")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("FORMAT")
+                .help("output format, default text, json prints one JSON document on stdout and progress on stderr")
+                .possible_values(&["text", "json"])
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("retries")
+                .long("retries")
+                .value_name("NUMBER")
+                .help("max retries for network errors and 5xx responses, default 3, 0 disables retry")
+                .takes_value(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("retryDelay")
+                .long("retry-delay")
+                .value_name("SECONDS")
+                .help("base delay before the first retry, doubled on each retry, default 1")
+                .takes_value(true)
+                .global(true),
+        )
+        .subcommand(
+            SubCommand::with_name("upload")
+                .about("upload one or more ipa or apk files")
+                .arg(
+                    Arg::with_name("file")
+                        .value_name("FILE")
+                        .help("the ipa or apk files to upload, quoted globs like 'build/**/*.apk' are expanded")
+                        .required_unless("watch")
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("watch")
+                        .long("watch")
+                        .value_name("DIR")
+                        .help("keep watching DIR and upload new packages once they are fully written")
+                        .takes_value(true)
+                        .conflicts_with("file"),
+                )
+                .arg(
+                    Arg::with_name("pattern")
+                        .long("pattern")
+                        .value_name("GLOB")
+                        .help("file name pattern for --watch, like '*-release.apk', default all ipa and apk")
+                        .takes_value(true)
+                        .requires("watch"),
+                )
+                .arg(
+                    Arg::with_name("settle")
+                        .long("settle")
+                        .value_name("SECONDS")
+                        .help("a file is uploaded after its size stays unchanged this long, default 3")
+                        .takes_value(true)
+                        .requires("watch"),
+                )
+                .arg(
+                    Arg::with_name("ignoreExisting")
+                        .long("ignore-existing")
+                        .help("record packages already in DIR as uploaded instead of uploading them")
+                        .requires("watch"),
+                )
+                .arg(
+                    Arg::with_name("parallel")
+                        .long("parallel")
+                        .value_name("NUMBER")
+                        .help("max files uploaded at the same time, default 3")
+                        .takes_value(true),
+                )
+                .args(&upload_args()),
+        )
+        .subcommand(
+            SubCommand::with_name("apps")
+                .about("manage apps")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("list my apps")
+                        .arg(
+                            Arg::with_name("page")
+                                .long("page")
+                                .value_name("NUMBER")
+                                .help("page number, default 1")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("all")
+                                .long("all")
+                                .help("fetch all pages")
+                                .conflicts_with("page"),
+                        )
+                        .arg(
+                            Arg::with_name("name")
+                                .long("name")
+                                .value_name("TEXT")
+                                .help("only apps whose name contains TEXT, case insensitive")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("type")
+                                .long("type")
+                                .value_name("TYPE")
+                                .help("only ios or android apps")
+                                .possible_values(&["ios", "android"])
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name("since")
+                                .long("since")
+                                .value_name("DATE")
+                                .help("only apps updated on or after DATE, format: yyyy-MM-dd")
+                                .takes_value(true),
+                        )
+                        .args(&table_args(
+                            "comma separated columns: key, name, identifier, type, version, build, size, date, url, default name,type,version,build,size,date,url",
+                        )),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("delete an app with all of its builds")
+                        .arg(
+                            Arg::with_name("appKey")
+                                .value_name("APP_KEY")
+                                .help("app key that you want to delete")
+                                .required(true),
+                        )
+                        .args(&delete_args()),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("builds")
+                .about("manage builds")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("list all builds of an app")
+                        .arg(
+                            Arg::with_name("appKey")
+                                .long("app-key")
+                                .value_name("APP_KEY")
+                                .help("app key whose builds you want to list")
+                                .takes_value(true)
+                                .required(true),
+                        )
+                        .args(&table_args(
+                            "comma separated columns: key, name, version, build, size, date, description, url, default version,build,date,size,description,key",
+                        )),
+                )
+                .subcommand(
+                    SubCommand::with_name("info").about("get build info").arg(
+                        Arg::with_name("buildKey")
+                            .value_name("BUILD_KEY")
+                            .required(true),
+                    ),
+                )
+                .subcommand(
+                    SubCommand::with_name("delete")
+                        .about("delete a build")
+                        .arg(
+                            Arg::with_name("buildKey")
+                                .value_name("BUILD_KEY")
+                                .help("build key that you want to delete")
+                                .required(true),
+                        )
+                        .args(&delete_args()),
+                ),
+        )
+        .subcommand(SubCommand::with_name("check").about("check network"))
+        .subcommand(config_cli())
+        .setting(AppSettings::ArgRequiredElseHelp);
+
+    // 旧版本不带子命令的参数, 仍然可以使用但不在帮助中显示
+    legacy_args()
+        .into_iter()
+        .chain(upload_args())
+        .chain(delete_args())
+        .fold(app, |app, arg| app.arg(arg.hidden(true)))
+}
+
+// upload 子命令和旧的上传参数共用
+fn upload_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("channel")
+            .short("c")
+            .long("channel")
+            .value_name("STRING")
+            .help("build channel shortcut")
+            .takes_value(true),
+        Arg::with_name("description")
+            .short("d")
+            .long("description")
+            .value_name("STRING")
+            .help("build update description")
+            .takes_value(true),
+        Arg::with_name("installEndDate")
+            .short("e")
+            .long("installEndDate")
+            .value_name("STRING")
+            .help("build install end date, format: yyyy-MM-dd, or relative to the start date like +7d, +2w")
+            .takes_value(true),
+        Arg::with_name("installDate")
+            .short("i")
+            .long("installDate")
+            .value_name("NUMBER")
+            .help("build install date, 1=installStartDate~installEndDate, 2=forever, default 1 if installEndDate is given")
+            .possible_values(&["1", "2"])
+            .takes_value(true),
+        Arg::with_name("installStartDate")
+            .short("s")
+            .long("installStartDate")
+            .value_name("STRING")
+            .help("build install start date, format: yyyy-MM-dd, default today")
+            .takes_value(true),
+        Arg::with_name("password")
+            .short("p")
+            .long("password")
+            .value_name("STRING")
+            .help("build password, required by and only valid with installType=2")
+            .takes_value(true),
+        Arg::with_name("installType")
+            .short("t")
+            .long("installType")
+            .value_name("NUMBER")
+            .help("build install type, 1=public, 2=password, 3=invite")
+            .possible_values(&["1", "2", "3", "public", "password", "invite"])
+            .takes_value(true),
+        Arg::with_name("checkDuplicate")
+            .long("check-duplicate")
+            .help("skip upload if a build with the same version, build number and file size already exists on pgyer, warn if only the version matches"),
+        Arg::with_name("failOnDuplicate")
+            .long("fail-on-duplicate")
+            .help("fail if a build with the same version and build number already exists on pgyer"),
+        Arg::with_name("noWait")
+            .long("no-wait")
+            .help("return the build key right after upload without waiting for processing"),
+        Arg::with_name("pollInterval")
+            .long("poll-interval")
+            .value_name("SECONDS")
+            .help("initial interval between build info polls, grows on each poll, default 1")
+            .takes_value(true),
+        Arg::with_name("waitTimeout")
+            .long("wait-timeout")
+            .value_name("SECONDS")
+            .help("max time to wait for build processing, default 600")
+            .takes_value(true),
+    ]
+}
+
+// 列表共用的表格参数
+fn table_args(columns_help: &'static str) -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("columns")
+            .long("columns")
+            .value_name("COLUMNS")
+            .help(columns_help)
+            .takes_value(true),
+        Arg::with_name("sort")
+            .long("sort")
+            .value_name("FIELD")
+            .help("sort by date (newest first), name or size (largest first)")
+            .possible_values(&["date", "name", "size"])
+            .takes_value(true),
+    ]
+}
+
+// 删除应用和构建共用
+fn delete_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("yes")
+            .short("y")
+            .long("yes")
+            .help("delete without confirmation, required in non-interactive mode"),
+        Arg::with_name("dryRun")
+            .long("dry-run")
+            .help("only print what would be deleted"),
+    ]
+}
+
+fn legacy_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("check").long("check").help("check network"),
+        Arg::with_name("file")
+            .short("f")
+            .long("file")
+            .value_name("FILE")
+            .help("Sets the upload file")
+            .takes_value(true),
+        Arg::with_name("api_key")
+            .short("k")
+            .long("key")
+            .value_name("STRING")
+            .help("Sets the api key")
+            .takes_value(true),
+        Arg::with_name("list")
+            .short("l")
+            .long("list")
+            .help("list my apps"),
+        Arg::with_name("page")
+            .long("page")
+            .value_name("NUMBER")
+            .help("page number")
+            .takes_value(true),
+        Arg::with_name("appKey")
+            .short("r")
+            .long("remove")
+            .value_name("STRING")
+            .help("app key that you want to delete")
+            .takes_value(true),
+        Arg::with_name("buildKey")
+            .long("removeBuild")
+            .value_name("STRING")
+            .help("build key that you want to delete")
+            .takes_value(true),
+        Arg::with_name("info")
+            .long("info")
+            .value_name("STRING")
+            .help("get build info with build key")
+            .takes_value(true),
+    ]
+}
+
+fn config_cli() -> App<'static, 'static> {
+    let key = || {
+        Arg::with_name("key")
+            .value_name("KEY")
+            .help("api_key, channel, install_type, password or description")
+            .required(true)
+    };
+    let show_secrets = || {
+        Arg::with_name("showSecrets")
+            .long("show-secrets")
+            .help("print api_key and password without masking")
+    };
+
+    SubCommand::with_name("config")
+        .about("manage the config file")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("get")
+                .about("print a value of the profile")
+                .arg(key())
+                .arg(show_secrets()),
+        )
+        .subcommand(
+            SubCommand::with_name("set")
+                .about("set a value of the profile, the profile is created if missing")
+                .arg(key())
+                .arg(Arg::with_name("value").value_name("VALUE").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("unset")
+                .about("remove a value from the profile")
+                .arg(key()),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("print all values of the profile")
+                .arg(show_secrets()),
+        )
+        .subcommand(SubCommand::with_name("path").about("print the config file path"))
+        .subcommand(SubCommand::with_name("profiles").about("list all profiles"))
+        .subcommand(
+            SubCommand::with_name("use")
+                .about("set the default profile")
+                .arg(Arg::with_name("name").value_name("NAME").required(true)),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_do_not_overflow() {
+        let max = u32::MAX.to_string();
+        let matches = get_app_cli().get_matches_from(vec!["pgyer", "--retries", &max]);
+        assert_eq!(retry_policy(&matches).unwrap().max_attempts, u32::MAX);
+
+        let matches = get_app_cli().get_matches_from(vec!["pgyer", "--retries", "0"]);
+        assert_eq!(retry_policy(&matches).unwrap().max_attempts, 1);
+    }
+}
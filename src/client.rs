@@ -0,0 +1,246 @@
+use std::time::Duration;
+
+use futures_util::{stream, Stream, TryStreamExt};
+use reqwest::{multipart::Form, Client};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{
+    error::{PgyerError, Result},
+    models::{ApiEnvelope, AppListPage, AppSummary, BuildInfo, BuildListPage, CosToken},
+    retry::RetryPolicy,
+};
+
+pub const PGYER_API_ENDPOINT: &str = "https://www.pgyer.com/apiv2/app/";
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 蒲公英 API 客户端, 内部复用同一个连接池
+#[derive(Clone, Debug)]
+pub struct PgyerClient {
+    http: Client,
+    api_key: String,
+    base_url: String,
+    timeout: Duration,
+    retry: RetryPolicy,
+}
+
+#[derive(Debug)]
+pub struct PgyerClientBuilder {
+    api_key: String,
+    base_url: String,
+    timeout: Duration,
+    connect_timeout: Duration,
+    retry: RetryPolicy,
+}
+
+impl PgyerClientBuilder {
+    /// 接口地址, 默认为 https://www.pgyer.com/apiv2/app/
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.to_owned();
+        if !self.base_url.ends_with('/') {
+            self.base_url.push('/');
+        }
+        self
+    }
+
+    /// 单次接口请求的超时时间, 不影响安装包上传
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+
+    /// 接口请求以及上传到 cos 时使用的重试策略
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn build(self) -> Result<PgyerClient> {
+        let http = Client::builder()
+            .connect_timeout(self.connect_timeout)
+            .build()?;
+
+        Ok(PgyerClient {
+            http,
+            api_key: self.api_key,
+            base_url: self.base_url,
+            timeout: self.timeout,
+            retry: self.retry,
+        })
+    }
+}
+
+impl PgyerClient {
+    pub fn builder(api_key: &str) -> PgyerClientBuilder {
+        PgyerClientBuilder {
+            api_key: api_key.to_owned(),
+            base_url: PGYER_API_ENDPOINT.to_owned(),
+            timeout: DEFAULT_TIMEOUT,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    pub fn new(api_key: &str) -> Result<PgyerClient> {
+        PgyerClient::builder(api_key).build()
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.retry
+    }
+
+    /// 底层的 HTTP 客户端, 上传到 cos 时复用同一个连接池
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    /// 获取上传到 cos 所需的签名, `params` 为 buildChannelShortcut 等可选的构建参数
+    pub async fn get_cos_token(
+        &self,
+        build_type: &str,
+        params: &[(&str, &str)],
+    ) -> Result<CosToken> {
+        let mut pairs = vec![("buildType", build_type)];
+        pairs.extend_from_slice(params);
+        into_data(self.post("getCOSToken", &pairs).await?)
+    }
+
+    /// 查询构建信息, 服务端处理中时 code 为 1246/1247 且 data 为空, 需要调用方自行判断
+    pub async fn build_info(&self, build_key: &str) -> Result<ApiEnvelope<BuildInfo>> {
+        let res = self.post("buildInfo", &[("buildKey", build_key)]).await?;
+        let data = if res.is_success() {
+            Some(serde_json::from_value::<BuildInfo>(
+                res.data.unwrap_or_default(),
+            )?)
+        } else {
+            None
+        };
+
+        Ok(ApiEnvelope {
+            code: res.code,
+            message: res.message,
+            data,
+        })
+    }
+
+    pub async fn list_my(&self, page: u32) -> Result<AppListPage> {
+        let page = page.to_string();
+        into_data(self.post("listMy", &[("page", &page)]).await?)
+    }
+
+    /// 依次请求 listMy 的每一页, 直到返回的 pageCount 为止
+    pub fn apps(&self) -> impl Stream<Item = Result<AppSummary>> + '_ {
+        stream::try_unfold(Some(1u32), move |page| async move {
+            let page = match page {
+                Some(page) => page,
+                None => return Ok::<_, PgyerError>(None),
+            };
+            let apps = self.list_my(page).await?;
+            // 空页也当作结束, 避免 pageCount 不准确时一直请求下去
+            let next =
+                (u64::from(page) < apps.page_count && !apps.list.is_empty()).then_some(page + 1);
+            Ok(Some((stream::iter(apps.list.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+    }
+
+    /// 某个应用的历史构建, 按上传时间倒序
+    pub async fn builds(&self, app_key: &str, page: u32) -> Result<BuildListPage> {
+        let page = page.to_string();
+        into_data(
+            self.post("builds", &[("appKey", app_key), ("page", &page)])
+                .await?,
+        )
+    }
+
+    /// 依次请求某个应用所有页的历史构建
+    pub fn app_builds<'a>(
+        &'a self,
+        app_key: &'a str,
+    ) -> impl Stream<Item = Result<BuildInfo>> + 'a {
+        stream::try_unfold(Some(1u32), move |page| async move {
+            let page = match page {
+                Some(page) => page,
+                None => return Ok::<_, PgyerError>(None),
+            };
+            let builds = self.builds(app_key, page).await?;
+            let next = (u64::from(page) < builds.page_count && !builds.list.is_empty())
+                .then_some(page + 1);
+            Ok(Some((stream::iter(builds.list.into_iter().map(Ok)), next)))
+        })
+        .try_flatten()
+    }
+
+    /// 不重试, 服务端删除后返回 5xx 时重试会得到应用不存在的错误
+    pub async fn delete_app(&self, app_key: &str) -> Result<()> {
+        check_code(self.post_once("deleteApp", &[("appKey", app_key)]).await?)
+    }
+
+    /// 不重试, 原因同 [`PgyerClient::delete_app`]
+    pub async fn delete_build(&self, build_key: &str) -> Result<()> {
+        check_code(
+            self.post_once("buildDelete", &[("buildKey", build_key)])
+                .await?,
+        )
+    }
+
+    async fn post(&self, action: &str, pairs: &[(&str, &str)]) -> Result<ApiEnvelope<Value>> {
+        self.retry
+            .run(action, || self.post_once(action, pairs))
+            .await
+    }
+
+    async fn post_once(&self, action: &str, pairs: &[(&str, &str)]) -> Result<ApiEnvelope<Value>> {
+        let mut form = Form::new().text("_api_key", self.api_key.clone());
+        for (key, value) in pairs {
+            form = form.text(key.to_string(), value.to_string());
+        }
+
+        let url = format!("{}{}", self.base_url, action);
+        let res = self
+            .http
+            .post(&url)
+            .timeout(self.timeout)
+            .multipart(form)
+            .send()
+            .await?;
+
+        match res.status() {
+            reqwest::StatusCode::OK => {
+                let body = res.text().await?;
+                Ok(serde_json::from_str(body.as_str())?)
+            }
+            status => Err(PgyerError::Http {
+                status: status.as_u16(),
+                body: res.text().await.unwrap_or_default(),
+            }),
+        }
+    }
+}
+
+// 接口返回 code 不为 0 时转换为错误
+fn check_code(res: ApiEnvelope<Value>) -> Result<()> {
+    if res.is_success() {
+        Ok(())
+    } else {
+        Err(res.into())
+    }
+}
+
+fn into_data<T: DeserializeOwned>(res: ApiEnvelope<Value>) -> Result<T> {
+    if !res.is_success() {
+        return Err(res.into());
+    }
+
+    Ok(serde_json::from_value(res.data.unwrap_or_default())?)
+}
@@ -0,0 +1,532 @@
+//! 本地配置文件, 位于 `$XDG_CONFIG_HOME/pgyer-uploader/config.toml`
+//!
+//! 配置按 profile 分组, 每个 profile 保存一个 api_key 以及上传时的默认参数
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    credential::{self, StoreKind},
+    error::{PgyerError, Result},
+    options::InstallType,
+    output::status,
+    package::PackageInfo,
+};
+
+pub const DEFAULT_PROFILE: &str = "default";
+const CONFIG_DIR: &str = "pgyer-uploader";
+const CONFIG_FILE: &str = "config.toml";
+// 旧版本保存 api_key 的 sled 数据库, 位于当前目录下
+const LEGACY_DB: &str = "my_db";
+const LEGACY_API_KEY: &str = "_api_key";
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Config {
+    /// 未指定 --profile 时使用的 profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default_profile: Option<String>,
+    /// api_key 的保存方式, 默认加密保存
+    #[serde(default, skip_serializing_if = "is_default_store")]
+    pub credential_store: StoreKind,
+    /// 保存加密口令的文件, 用于无法输入口令的环境
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_file: Option<PathBuf>,
+    /// 已经导入过旧版本 my_db 中的 api_key, 不再重复导入
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub migrated_legacy_db: bool,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    // 读取时的文件路径, 保存时写回同一个文件
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+    /// 默认的渠道短链接
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<String>,
+    /// 默认的安装方式, 1=public, 2=password, 3=invite
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// 更新说明模板, 支持 {version} {build} {file} {date} 占位符
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// profile 中可以通过 `config set` 修改的配置项
+pub const PROFILE_KEYS: [&str; 5] = [
+    "api_key",
+    "channel",
+    "install_type",
+    "password",
+    "description",
+];
+/// 不属于某个 profile 的全局配置项
+pub const GLOBAL_KEYS: [&str; 2] = ["credential_store", "key_file"];
+// 输出时需要隐藏的配置项
+const SECRET_KEYS: [&str; 2] = ["api_key", "password"];
+
+/// 是否为需要隐藏的配置项
+pub fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key)
+}
+
+/// 隐藏敏感信息, 较长的值保留首尾各 4 个字符方便区分
+pub fn mask_secret(value: &str) -> String {
+    let chars: Vec<char> = value.chars().collect();
+    if chars.len() < 16 {
+        return "********".to_owned();
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}********{}", head, tail)
+}
+
+/// 蒲公英的 api_key 固定为 32 位
+pub fn validate_api_key(api_key: &str) -> Result<()> {
+    if api_key.len() != 32 {
+        return Err(PgyerError::Validation("api_key is invalid".to_owned()));
+    }
+    Ok(())
+}
+
+/// 写入只允许当前用户读写的文件, 新文件创建时就是 0600, 不会有其他用户可以读取的时间窗口
+pub(crate) fn write_private(path: &Path, content: &str) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    // 已经存在的文件打开时不会修改权限, 在写入内容之前修改
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+fn is_default_store(kind: &StoreKind) -> bool {
+    *kind == StoreKind::default()
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn unknown_key(key: &str) -> PgyerError {
+    PgyerError::Validation(format!(
+        "未知的配置项: {}, 可用的配置项: {}, {}",
+        key,
+        PROFILE_KEYS.join(", "),
+        GLOBAL_KEYS.join(", ")
+    ))
+}
+
+impl Profile {
+    pub fn get(&self, key: &str) -> Result<Option<&str>> {
+        let value = match key {
+            "api_key" => &self.api_key,
+            "channel" => &self.channel,
+            "install_type" => &self.install_type,
+            "password" => &self.password,
+            "description" => &self.description,
+            _ => return Err(unknown_key(key)),
+        };
+        Ok(value.as_deref())
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "api_key" => validate_api_key(value)?,
+            "install_type" => InstallType::check_name(value)?,
+            _ => {}
+        }
+        *self.field_mut(key)? = Some(value.to_owned());
+        Ok(())
+    }
+
+    /// 删除配置项, 返回原来的值
+    pub fn unset(&mut self, key: &str) -> Result<Option<String>> {
+        Ok(self.field_mut(key)?.take())
+    }
+
+    /// 已设置的配置项, 按 PROFILE_KEYS 的顺序
+    pub fn entries(&self) -> Vec<(&'static str, &str)> {
+        PROFILE_KEYS
+            .iter()
+            .filter_map(|key| Some((*key, self.get(key).ok()??)))
+            .collect()
+    }
+
+    fn field_mut(&mut self, key: &str) -> Result<&mut Option<String>> {
+        match key {
+            "api_key" => Ok(&mut self.api_key),
+            "channel" => Ok(&mut self.channel),
+            "install_type" => Ok(&mut self.install_type),
+            "password" => Ok(&mut self.password),
+            "description" => Ok(&mut self.description),
+            _ => Err(unknown_key(key)),
+        }
+    }
+
+    /// 用安装包信息填充更新说明模板
+    pub fn render_description(&self, file: &Path, info: Option<&PackageInfo>) -> Option<String> {
+        let template = self.description.as_ref()?;
+        let file_name = file
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (version, build) = info
+            .map(|info| (info.version_name.as_str(), info.version_code.as_str()))
+            .unwrap_or_default();
+
+        Some(
+            template
+                .replace("{version}", version)
+                .replace("{build}", build)
+                .replace("{file}", &file_name)
+                .replace("{date}", &Local::now().format("%Y-%m-%d").to_string()),
+        )
+    }
+}
+
+impl Config {
+    /// 配置文件路径
+    pub fn path() -> Result<PathBuf> {
+        let dir = dirs::config_dir()
+            .ok_or_else(|| PgyerError::Config("无法确定配置文件目录".to_owned()))?;
+        Ok(dir.join(CONFIG_DIR).join(CONFIG_FILE))
+    }
+
+    /// 读取配置文件, 第一次使用时会导入旧版本保存在 my_db 里的 api_key, 只导入一次
+    pub fn load() -> Result<Config> {
+        Config::load_migrating(&Config::path()?, Path::new(LEGACY_DB))
+    }
+
+    fn load_migrating(path: &Path, legacy_db: &Path) -> Result<Config> {
+        let mut config = Config::load_from(path)?;
+        if config.migrated_legacy_db || !legacy_db.is_dir() {
+            return Ok(config);
+        }
+
+        match config.migrate_legacy_db(legacy_db) {
+            Ok(imported) => {
+                // 明文保存时存储后端会修改配置文件
+                config = Config::load_from(path)?;
+                config.migrated_legacy_db = true;
+                config.save()?;
+                if let Some(store) = imported {
+                    status!(
+                        "已将 {} 中的 api_key 导入到 {} 存储中, {} 目录可以删除了",
+                        LEGACY_DB,
+                        store,
+                        LEGACY_DB
+                    );
+                }
+            }
+            // 没有口令等情况下不影响当前的操作, 下次运行时再导入
+            Err(err) => status!("导入 {} 中的 api_key 失败: {}", LEGACY_DB, err),
+        }
+        Ok(config)
+    }
+
+    /// 文件不存在时返回空配置
+    pub fn load_from(path: &Path) -> Result<Config> {
+        let mut config = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            toml::from_str(&content).map_err(|err| {
+                PgyerError::Config(format!("配置文件 {} 格式不正确: {}", path.display(), err))
+            })?
+        } else {
+            Config::default()
+        };
+        config.file = Some(path.to_owned());
+        Ok(config)
+    }
+
+    /// 读取时使用的配置文件路径, 不是从文件读取的配置为默认路径
+    pub fn file(&self) -> Result<PathBuf> {
+        match &self.file {
+            Some(file) => Ok(file.clone()),
+            None => Config::path(),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&self.file()?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|err| PgyerError::Config(format!("保存配置失败: {}", err)))?;
+        // 配置里有 api_key, 只允许当前用户读写
+        write_private(path, &content)
+    }
+
+    /// 当前使用的 profile 名称, 优先使用 --profile 指定的
+    pub fn active_profile_name(&self, requested: Option<&str>) -> String {
+        requested
+            .or(self.default_profile.as_deref())
+            .unwrap_or(DEFAULT_PROFILE)
+            .to_owned()
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.get(name)
+    }
+
+    /// 不存在时创建一个空的 profile
+    pub fn profile_mut(&mut self, name: &str) -> &mut Profile {
+        self.profiles.entry(name.to_owned()).or_default()
+    }
+
+    /// 读取全局配置项
+    pub fn option(&self, key: &str) -> Result<Option<String>> {
+        match key {
+            "credential_store" => Ok(Some(self.credential_store.as_str().to_owned())),
+            "key_file" => Ok(self
+                .key_file
+                .as_ref()
+                .map(|path| path.display().to_string())),
+            _ => Err(unknown_key(key)),
+        }
+    }
+
+    pub fn set_option(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "credential_store" => self.credential_store = StoreKind::parse(value)?,
+            "key_file" => self.key_file = Some(PathBuf::from(value)),
+            _ => return Err(unknown_key(key)),
+        }
+        Ok(())
+    }
+
+    /// 恢复为默认值, 返回之前是否设置过
+    pub fn unset_option(&mut self, key: &str) -> Result<bool> {
+        match key {
+            "credential_store" => {
+                let changed = !is_default_store(&self.credential_store);
+                self.credential_store = StoreKind::default();
+                Ok(changed)
+            }
+            "key_file" => Ok(self.key_file.take().is_some()),
+            _ => Err(unknown_key(key)),
+        }
+    }
+
+    /// 修改未指定 --profile 时使用的 profile, profile 必须已经存在
+    pub fn use_profile(&mut self, name: &str) -> Result<()> {
+        if !self.profiles.contains_key(name) {
+            return Err(PgyerError::Config(format!(
+                "profile {} 不存在, 请先使用 config set --profile {} 添加配置",
+                name, name
+            )));
+        }
+        self.default_profile = Some(name.to_owned());
+        Ok(())
+    }
+
+    // 从旧版本的 my_db 导入到配置的存储后端, 已经有 api_key 时不覆盖
+    //
+    // 导入后返回存储后端的名称
+    fn migrate_legacy_db(&self, legacy_db: &Path) -> Result<Option<String>> {
+        let db = sled::open(legacy_db)?;
+        let api_key = match db.get(LEGACY_API_KEY)? {
+            Some(value) => String::from_utf8_lossy(&value).into_owned(),
+            None => return Ok(None),
+        };
+
+        let name = self.active_profile_name(None);
+        let has_plaintext = self
+            .profile(&name)
+            .is_some_and(|profile| profile.api_key.is_some());
+        let store = credential::from_config(self)?;
+        if has_plaintext || store.get(&name)?.is_some() {
+            return Ok(None);
+        }
+        store.set(&name, &api_key)?;
+        Ok(Some(store.name().to_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::package::PackageKind;
+
+    const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn profile_values() {
+        let mut config = Config::default();
+        let profile = config.profile_mut("beta");
+        profile.set("channel", "beta").unwrap();
+        profile.set("install_type", "password").unwrap();
+        profile.set("password", "secret").unwrap();
+        assert!(profile.set("api_key", "short").is_err());
+        assert!(profile.set("install_type", "4").is_err());
+        assert!(profile.set("unknown", "value").is_err());
+        assert_eq!(
+            profile.entries(),
+            [
+                ("channel", "beta"),
+                ("install_type", "password"),
+                ("password", "secret")
+            ]
+        );
+        assert_eq!(
+            profile.unset("password").unwrap().as_deref(),
+            Some("secret")
+        );
+        assert_eq!(profile.unset("password").unwrap(), None);
+    }
+
+    #[test]
+    fn switch_profiles() {
+        let mut config = Config::default();
+        assert_eq!(config.active_profile_name(None), DEFAULT_PROFILE);
+        assert!(config.use_profile("beta").is_err());
+
+        config.profile_mut("beta");
+        config.use_profile("beta").unwrap();
+        assert_eq!(config.active_profile_name(None), "beta");
+        assert_eq!(config.active_profile_name(Some("prod")), "prod");
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("nested").join(CONFIG_FILE);
+        let mut config = Config::load_from(&path).unwrap();
+        config.profile_mut("beta").set("channel", "beta").unwrap();
+        config.set_option("credential_store", "plaintext").unwrap();
+        config.save().unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.credential_store, StoreKind::Plaintext);
+        assert_eq!(
+            config.profile("beta").unwrap().channel.as_deref(),
+            Some("beta")
+        );
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn restrict_existing_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        fs::write(&path, "").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, "default_profile = \"beta\"\n").unwrap();
+        assert_eq!(
+            fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+            0o600
+        );
+    }
+
+    #[test]
+    fn render_description_template() {
+        let profile = Profile {
+            description: Some("{file} {version} ({build})".to_owned()),
+            ..Profile::default()
+        };
+        let info = PackageInfo {
+            kind: PackageKind::Apk,
+            identifier: "com.example.demo".to_owned(),
+            version_name: "1.2.3".to_owned(),
+            version_code: "45".to_owned(),
+            display_name: None,
+            min_os_version: None,
+            target_sdk_version: None,
+            provisioning: None,
+        };
+        let file = Path::new("out/app.apk");
+        assert_eq!(
+            profile.render_description(file, Some(&info)).as_deref(),
+            Some("app.apk 1.2.3 (45)")
+        );
+        assert_eq!(
+            profile.render_description(file, None).as_deref(),
+            Some("app.apk  ()")
+        );
+        assert_eq!(Profile::default().render_description(file, None), None);
+    }
+
+    #[test]
+    fn mask_secrets() {
+        assert_eq!(mask_secret("short"), "********");
+        assert_eq!(mask_secret(API_KEY), "0123********cdef");
+        assert!(is_secret("api_key"));
+        assert!(is_secret("password"));
+        assert!(!is_secret("channel"));
+    }
+
+    #[test]
+    fn migrate_legacy_db_once() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(CONFIG_FILE);
+        let legacy_db = dir.path().join(LEGACY_DB);
+        {
+            let db = sled::open(&legacy_db).unwrap();
+            db.insert(LEGACY_API_KEY, API_KEY).unwrap();
+            db.flush().unwrap();
+        }
+        // sled 的后台线程在 drop 之后还会持有一会儿文件锁
+        let lock = fs::File::open(legacy_db.join("db")).unwrap();
+        while lock.try_lock().is_err() {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        drop(lock);
+        let config = Config {
+            credential_store: StoreKind::Plaintext,
+            ..Config::default()
+        };
+        config.save_to(&path).unwrap();
+
+        let config = Config::load_migrating(&path, &legacy_db).unwrap();
+        assert!(config.migrated_legacy_db);
+        let profile = config.profile(DEFAULT_PROFILE).unwrap();
+        assert_eq!(profile.api_key.as_deref(), Some(API_KEY));
+
+        // 删除后不会再次导入
+        let mut config = Config::load_from(&path).unwrap();
+        config
+            .profile_mut(DEFAULT_PROFILE)
+            .unset("api_key")
+            .unwrap();
+        config.save().unwrap();
+        let config = Config::load_migrating(&path, &legacy_db).unwrap();
+        assert_eq!(config.profile(DEFAULT_PROFILE).unwrap().api_key, None);
+    }
+}
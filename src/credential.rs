@@ -0,0 +1,414 @@
+//! api_key 的保存方式
+//!
+//! 默认保存在使用口令加密的 `credentials.enc` 中, 明文保存到配置文件需要显式开启。
+//! 需要接入其他密钥管理服务时实现 [`CredentialStore`], 并通过 [`set_store`] 注册
+
+use std::{
+    collections::BTreeMap,
+    env, fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use argon2::Argon2;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng},
+    XChaCha20Poly1305, XNonce,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::{write_private, Config},
+    error::{PgyerError, Result},
+};
+
+/// 优先于保存的 api_key 使用的环境变量
+pub const API_KEY_ENV: &str = "PGYER_API_KEY";
+/// 加密文件的口令
+pub const PASSPHRASE_ENV: &str = "PGYER_PASSPHRASE";
+/// 保存加密文件口令的文件, 也可以在配置文件中通过 key_file 指定
+pub const KEY_FILE_ENV: &str = "PGYER_KEY_FILE";
+
+const CREDENTIALS_FILE: &str = "credentials.enc";
+const FORMAT_VERSION: u32 = 1;
+const SALT_LEN: usize = 16;
+
+/// api_key 的存储后端, 按 profile 保存
+pub trait CredentialStore: Send + Sync {
+    /// 用于提示信息的名称
+    fn name(&self) -> &str;
+    fn get(&self, profile: &str) -> Result<Option<String>>;
+    fn set(&self, profile: &str, api_key: &str) -> Result<()>;
+    /// 删除 api_key, 返回之前是否存在
+    fn delete(&self, profile: &str) -> Result<bool>;
+}
+
+/// 配置文件中 credential_store 的取值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreKind {
+    #[default]
+    Encrypted,
+    Plaintext,
+}
+
+impl StoreKind {
+    pub fn parse(value: &str) -> Result<StoreKind> {
+        match value {
+            "encrypted" => Ok(StoreKind::Encrypted),
+            "plaintext" => Ok(StoreKind::Plaintext),
+            _ => Err(PgyerError::Validation(
+                "credential_store 只能是 encrypted 或 plaintext".to_owned(),
+            )),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StoreKind::Encrypted => "encrypted",
+            StoreKind::Plaintext => "plaintext",
+        }
+    }
+}
+
+static CUSTOM_STORE: RwLock<Option<Arc<dyn CredentialStore>>> = RwLock::new(None);
+
+/// 注册自定义的存储后端, 之后读取和保存 api_key 都使用它, 不再使用配置文件中的 credential_store
+///
+/// 需要在创建客户端之前调用, 已经读取过的 api_key 不会重新读取
+pub fn set_store(store: Arc<dyn CredentialStore>) {
+    *CUSTOM_STORE.write().unwrap() = Some(store);
+}
+
+/// 注册的存储后端, 没有注册时按配置文件中的 credential_store 创建
+pub fn from_config(config: &Config) -> Result<Arc<dyn CredentialStore>> {
+    if let Some(store) = CUSTOM_STORE.read().unwrap().as_ref() {
+        return Ok(store.clone());
+    }
+    match config.credential_store {
+        StoreKind::Plaintext => Ok(Arc::new(PlaintextStore::new(config.file()?))),
+        StoreKind::Encrypted => {
+            let path = config.file()?.with_file_name(CREDENTIALS_FILE);
+            let key_file = env::var_os(KEY_FILE_ENV)
+                .map(PathBuf::from)
+                .or_else(|| config.key_file.clone());
+            Ok(Arc::new(EncryptedFileStore::new(path, key_file)))
+        }
+    }
+}
+
+/// api_key 是否明文保存在配置文件的 profile 中
+pub fn is_plaintext(config: &Config) -> bool {
+    CUSTOM_STORE.read().unwrap().is_none() && config.credential_store == StoreKind::Plaintext
+}
+
+/// 读取 api_key, 环境变量 PGYER_API_KEY 优先
+pub fn resolve_api_key(store: &dyn CredentialStore, profile: &str) -> Result<Option<String>> {
+    match env::var(API_KEY_ENV) {
+        Ok(api_key) if !api_key.is_empty() => Ok(Some(api_key)),
+        _ => store.get(profile),
+    }
+}
+
+/// 明文保存在配置文件的 profile 中
+pub struct PlaintextStore {
+    path: PathBuf,
+}
+
+impl PlaintextStore {
+    pub fn new(path: PathBuf) -> PlaintextStore {
+        PlaintextStore { path }
+    }
+}
+
+impl CredentialStore for PlaintextStore {
+    fn name(&self) -> &str {
+        "plaintext"
+    }
+
+    fn get(&self, profile: &str) -> Result<Option<String>> {
+        let config = Config::load_from(&self.path)?;
+        Ok(config
+            .profile(profile)
+            .and_then(|profile| profile.api_key.clone()))
+    }
+
+    fn set(&self, profile: &str, api_key: &str) -> Result<()> {
+        let mut config = Config::load_from(&self.path)?;
+        config.profile_mut(profile).api_key = Some(api_key.to_owned());
+        config.save_to(&self.path)
+    }
+
+    fn delete(&self, profile: &str) -> Result<bool> {
+        let mut config = Config::load_from(&self.path)?;
+        let removed = config
+            .profiles
+            .get_mut(profile)
+            .and_then(|profile| profile.api_key.take())
+            .is_some();
+        if removed {
+            config.save_to(&self.path)?;
+        }
+        Ok(removed)
+    }
+}
+
+// 加密文件的内容, 密钥由口令经过 argon2id 派生
+#[derive(Deserialize, Serialize)]
+struct EncryptedFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// 使用口令加密的文件, 口令依次从 PGYER_PASSPHRASE, key_file 和终端输入中获取
+pub struct EncryptedFileStore {
+    path: PathBuf,
+    key_file: Option<PathBuf>,
+}
+
+impl EncryptedFileStore {
+    pub fn new(path: PathBuf, key_file: Option<PathBuf>) -> EncryptedFileStore {
+        EncryptedFileStore { path, key_file }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn passphrase(&self, confirm: bool) -> Result<String> {
+        // 空口令派生出的密钥等于没有加密
+        if let Ok(passphrase) = env::var(PASSPHRASE_ENV) {
+            if passphrase.is_empty() {
+                return Err(PgyerError::Validation(format!(
+                    "{} 不能为空",
+                    PASSPHRASE_ENV
+                )));
+            }
+            return Ok(passphrase);
+        }
+
+        if let Some(key_file) = &self.key_file {
+            let content = fs::read_to_string(key_file).map_err(|err| {
+                PgyerError::Config(format!(
+                    "读取 key_file {} 失败: {}",
+                    key_file.display(),
+                    err
+                ))
+            })?;
+            let passphrase = content.trim_end_matches(['\r', '\n']);
+            if passphrase.is_empty() {
+                return Err(PgyerError::Validation(format!(
+                    "key_file {} 中的口令为空",
+                    key_file.display()
+                )));
+            }
+            return Ok(passphrase.to_owned());
+        }
+
+        if !io::stdin().is_terminal() {
+            return Err(PgyerError::Config(format!(
+                "api_key 已加密保存, 请通过 {} 或 key_file 提供口令",
+                PASSPHRASE_ENV
+            )));
+        }
+
+        let passphrase = rpassword::prompt_password("请输入 api_key 的加密口令: ")?;
+        if passphrase.is_empty() {
+            return Err(PgyerError::Validation("口令不能为空".to_owned()));
+        }
+        if confirm && rpassword::prompt_password("请再次输入口令: ")? != passphrase {
+            return Err(PgyerError::Validation("两次输入的口令不一致".to_owned()));
+        }
+        Ok(passphrase)
+    }
+
+    fn read(&self, passphrase: &str) -> Result<BTreeMap<String, String>> {
+        let content = fs::read_to_string(&self.path)?;
+        let file: EncryptedFile = toml::from_str(&content).map_err(|err| self.corrupted(err))?;
+        if file.version != FORMAT_VERSION {
+            return Err(self.corrupted(format!("不支持的版本 {}", file.version)));
+        }
+
+        let salt = STANDARD
+            .decode(file.salt)
+            .map_err(|err| self.corrupted(err))?;
+        let nonce = STANDARD
+            .decode(file.nonce)
+            .map_err(|err| self.corrupted(err))?;
+        let ciphertext = STANDARD
+            .decode(file.ciphertext)
+            .map_err(|err| self.corrupted(err))?;
+        if nonce.len() != 24 {
+            return Err(self.corrupted("nonce 长度不正确"));
+        }
+
+        let plaintext = cipher(passphrase, &salt)?
+            .decrypt(XNonce::from_slice(&nonce), ciphertext.as_slice())
+            .map_err(|_| PgyerError::Config("口令不正确或加密文件已损坏".to_owned()))?;
+        let plaintext = String::from_utf8(plaintext).map_err(|err| self.corrupted(err))?;
+        toml::from_str(&plaintext).map_err(|err| self.corrupted(err))
+    }
+
+    fn write(&self, passphrase: &str, keys: &BTreeMap<String, String>) -> Result<()> {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let plaintext = toml::to_string(keys)
+            .map_err(|err| PgyerError::Config(format!("保存 api_key 失败: {}", err)))?;
+        let ciphertext = cipher(passphrase, &salt)?
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| PgyerError::Config("加密 api_key 失败".to_owned()))?;
+
+        let file = EncryptedFile {
+            version: FORMAT_VERSION,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        };
+        let content = toml::to_string_pretty(&file)
+            .map_err(|err| PgyerError::Config(format!("保存 api_key 失败: {}", err)))?;
+        write_private(&self.path, &content)
+    }
+
+    fn corrupted(&self, err: impl std::fmt::Display) -> PgyerError {
+        PgyerError::Config(format!(
+            "加密文件 {} 格式不正确: {}",
+            self.path.display(),
+            err
+        ))
+    }
+}
+
+impl CredentialStore for EncryptedFileStore {
+    fn name(&self) -> &str {
+        "encrypted"
+    }
+
+    fn get(&self, profile: &str) -> Result<Option<String>> {
+        if !self.path.exists() {
+            return Ok(None);
+        }
+        Ok(self.read(&self.passphrase(false)?)?.remove(profile))
+    }
+
+    fn set(&self, profile: &str, api_key: &str) -> Result<()> {
+        // 第一次创建时需要确认口令
+        let exists = self.path.exists();
+        let passphrase = self.passphrase(!exists)?;
+        let mut keys = if exists {
+            self.read(&passphrase)?
+        } else {
+            BTreeMap::new()
+        };
+        keys.insert(profile.to_owned(), api_key.to_owned());
+        self.write(&passphrase, &keys)
+    }
+
+    fn delete(&self, profile: &str) -> Result<bool> {
+        if !self.path.exists() {
+            return Ok(false);
+        }
+        let passphrase = self.passphrase(false)?;
+        let mut keys = self.read(&passphrase)?;
+        if keys.remove(profile).is_none() {
+            return Ok(false);
+        }
+        self.write(&passphrase, &keys)?;
+        Ok(true)
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| PgyerError::Config(format!("生成密钥失败: {}", err)))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+    // 口令保存在 key_file 中的加密存储
+    fn encrypted_store(dir: &TempDir, passphrase: &str) -> EncryptedFileStore {
+        let key_file = dir.path().join(format!("key-{}", passphrase.len()));
+        fs::write(&key_file, format!("{}\n", passphrase)).unwrap();
+        EncryptedFileStore::new(dir.path().join(CREDENTIALS_FILE), Some(key_file))
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let store = encrypted_store(&dir, "passphrase");
+        assert_eq!(store.get("default").unwrap(), None);
+        store.set("default", API_KEY).unwrap();
+        store.set("beta", "beta-key").unwrap();
+        assert_eq!(store.get("default").unwrap().as_deref(), Some(API_KEY));
+        assert_eq!(store.get("beta").unwrap().as_deref(), Some("beta-key"));
+
+        // 文件里没有明文
+        let content = fs::read_to_string(store.path()).unwrap();
+        assert!(!content.contains(API_KEY));
+    }
+
+    #[test]
+    fn reject_wrong_passphrase() {
+        let dir = TempDir::new().unwrap();
+        encrypted_store(&dir, "passphrase")
+            .set("default", API_KEY)
+            .unwrap();
+        let err = encrypted_store(&dir, "wrong passphrase")
+            .get("default")
+            .unwrap_err();
+        assert!(matches!(err, PgyerError::Config(_)));
+    }
+
+    #[test]
+    fn reject_empty_key_file() {
+        let dir = TempDir::new().unwrap();
+        let store = encrypted_store(&dir, "");
+        assert!(matches!(
+            store.set("default", API_KEY),
+            Err(PgyerError::Validation(_))
+        ));
+        assert!(!store.path().exists());
+    }
+
+    #[test]
+    fn delete_encrypted() {
+        let dir = TempDir::new().unwrap();
+        let store = encrypted_store(&dir, "passphrase");
+        assert!(!store.delete("default").unwrap());
+        store.set("default", API_KEY).unwrap();
+        store.set("beta", "beta-key").unwrap();
+        assert!(store.delete("default").unwrap());
+        assert!(!store.delete("default").unwrap());
+        assert_eq!(store.get("default").unwrap(), None);
+        assert_eq!(store.get("beta").unwrap().as_deref(), Some("beta-key"));
+    }
+
+    #[test]
+    fn plaintext_round_trip() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let store = PlaintextStore::new(path.clone());
+        store.set("default", API_KEY).unwrap();
+        assert_eq!(store.get("default").unwrap().as_deref(), Some(API_KEY));
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(
+            config.profile("default").unwrap().api_key.as_deref(),
+            Some(API_KEY)
+        );
+        assert!(store.delete("default").unwrap());
+        assert!(!store.delete("default").unwrap());
+    }
+}
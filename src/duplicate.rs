@@ -0,0 +1,79 @@
+//! 上传前检查蒲公英上是否已经有相同的构建
+
+use std::pin::pin;
+
+use futures_util::TryStreamExt;
+
+use crate::{
+    client::PgyerClient,
+    error::Result,
+    filter::Platform,
+    models::{AppSummary, BuildInfo},
+    package::{PackageInfo, PackageKind},
+};
+
+#[derive(Debug, Clone)]
+pub enum DuplicateCheck {
+    /// 没有找到相同版本的构建
+    NotFound,
+    /// 版本号和构建号相同, 蒲公英没有返回文件哈希, 无法判断是否为同一个文件
+    SameVersion {
+        build: Box<BuildInfo>,
+        /// 文件大小也相同, 不同的文件也可能大小相同
+        same_size: bool,
+    },
+}
+
+/// 根据包名找到蒲公英上对应的应用, 再和它的历史构建逐个比较
+///
+/// 蒲公英接口没有返回文件哈希, 只能比较版本号和构建号, 有多个时优先返回文件大小相同的
+pub async fn find_duplicate(
+    client: &PgyerClient,
+    info: &PackageInfo,
+    file_size: u64,
+) -> Result<DuplicateCheck> {
+    let app = match find_app(client, info).await? {
+        Some(app) => app,
+        None => return Ok(DuplicateCheck::NotFound),
+    };
+
+    let mut same_version = None;
+    let mut builds = pin!(client.app_builds(&app.app_key));
+    while let Some(build) = builds.try_next().await? {
+        if build.build_version != info.version_name || build.build_version_no != info.version_code {
+            continue;
+        }
+
+        if build.build_file_size == file_size {
+            return Ok(DuplicateCheck::SameVersion {
+                build: Box::new(build),
+                same_size: true,
+            });
+        }
+        same_version.get_or_insert(build);
+    }
+
+    Ok(match same_version {
+        Some(build) => DuplicateCheck::SameVersion {
+            build: Box::new(build),
+            same_size: false,
+        },
+        None => DuplicateCheck::NotFound,
+    })
+}
+
+async fn find_app(client: &PgyerClient, info: &PackageInfo) -> Result<Option<AppSummary>> {
+    let build_type = match info.kind {
+        PackageKind::Ipa => Platform::Ios,
+        PackageKind::Apk => Platform::Android,
+    }
+    .build_type();
+
+    let mut apps = pin!(client.apps());
+    while let Some(app) = apps.try_next().await? {
+        if app.build_identifier == info.identifier && app.build_type == build_type {
+            return Ok(Some(app));
+        }
+    }
+    Ok(None)
+}
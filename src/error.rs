@@ -0,0 +1,154 @@
+use std::{error::Error, fmt, io};
+
+use crate::models::ApiEnvelope;
+
+pub type Result<T> = std::result::Result<T, PgyerError>;
+
+#[derive(Debug)]
+pub enum PgyerError {
+    /// 请求没有发出去或者连接中断
+    Network(reqwest::Error),
+    /// 服务端返回了非预期的 HTTP 状态码
+    Http {
+        status: u16,
+        body: String,
+    },
+    /// 蒲公英接口返回的 code 不为 0
+    Api {
+        code: i64,
+        message: String,
+    },
+    /// 参数或者文件不合法
+    Validation(String),
+    Io(io::Error),
+    /// 返回内容无法解析
+    Decode(serde_json::Error),
+    /// 上传成功但服务端处理失败
+    Processing(String),
+    /// 等待服务端处理超时
+    Timeout(String),
+    /// 蒲公英上已经存在相同版本号和构建号的构建
+    Duplicate {
+        build_key: String,
+    },
+    /// 配置文件读写失败
+    Config(String),
+    /// 批量上传时有文件上传失败, 每个文件的错误已经输出在汇总中
+    Batch {
+        failed: usize,
+        total: usize,
+    },
+}
+
+impl PgyerError {
+    /// 进程的退出码, json 模式下也用作错误的 code
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            PgyerError::Validation(_) => 2,
+            PgyerError::Network(_) => 3,
+            PgyerError::Http { .. } => 4,
+            PgyerError::Api { .. } => 5,
+            PgyerError::Io(_) => 6,
+            PgyerError::Decode(_) => 7,
+            PgyerError::Processing(_) => 8,
+            PgyerError::Timeout(_) => 9,
+            PgyerError::Duplicate { .. } => 10,
+            PgyerError::Config(_) => 11,
+            PgyerError::Batch { .. } => 12,
+        }
+    }
+}
+
+impl fmt::Display for PgyerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PgyerError::Network(err) => write!(f, "网络请求失败: {}", err),
+            PgyerError::Http { status, body } => {
+                write!(f, "Received response with status: {}", status)?;
+                if !body.is_empty() {
+                    write!(f, "\n{}", body)?;
+                }
+                Ok(())
+            }
+            PgyerError::Api { code, message } => write!(f, "{} (code: {})", message, code),
+            PgyerError::Validation(message) => write!(f, "{}", message),
+            PgyerError::Io(err) => write!(f, "读写文件失败: {}", err),
+            PgyerError::Decode(err) => write!(f, "解析返回数据失败: {}", err),
+            PgyerError::Processing(message) => write!(f, "{}", message),
+            PgyerError::Timeout(message) => write!(f, "{}", message),
+            PgyerError::Duplicate { build_key } => {
+                write!(
+                    f,
+                    "蒲公英上已存在相同版本号和构建号的构建, buildKey: {}",
+                    build_key
+                )
+            }
+            PgyerError::Config(message) => write!(f, "{}", message),
+            PgyerError::Batch { failed, total } => {
+                write!(f, "{}/{} 个文件上传失败", failed, total)
+            }
+        }
+    }
+}
+
+impl Error for PgyerError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            PgyerError::Network(err) => Some(err),
+            PgyerError::Io(err) => Some(err),
+            PgyerError::Decode(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for PgyerError {
+    fn from(err: reqwest::Error) -> Self {
+        PgyerError::Network(err)
+    }
+}
+
+impl From<io::Error> for PgyerError {
+    fn from(err: io::Error) -> Self {
+        PgyerError::Io(err)
+    }
+}
+
+impl From<notify::Error> for PgyerError {
+    fn from(err: notify::Error) -> Self {
+        match err.kind {
+            notify::ErrorKind::Io(err) => PgyerError::Io(err),
+            _ => PgyerError::Io(io::Error::other(err)),
+        }
+    }
+}
+
+impl From<sled::Error> for PgyerError {
+    fn from(err: sled::Error) -> Self {
+        PgyerError::Io(err.into())
+    }
+}
+
+impl From<zip::result::ZipError> for PgyerError {
+    fn from(err: zip::result::ZipError) -> Self {
+        match err {
+            zip::result::ZipError::Io(err) => PgyerError::Io(err),
+            err => PgyerError::Validation(format!("解析安装包失败: {}", err)),
+        }
+    }
+}
+
+impl From<serde_json::Error> for PgyerError {
+    fn from(err: serde_json::Error) -> Self {
+        PgyerError::Decode(err)
+    }
+}
+
+impl<T> From<ApiEnvelope<T>> for PgyerError {
+    fn from(res: ApiEnvelope<T>) -> Self {
+        PgyerError::Api {
+            code: res.code,
+            message: res.message,
+        }
+    }
+}
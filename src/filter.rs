@@ -0,0 +1,163 @@
+//! 应用列表的客户端过滤, 蒲公英的 listMy 接口不支持按条件查询
+
+use chrono::NaiveDate;
+
+use crate::{
+    error::{PgyerError, Result},
+    models::AppSummary,
+};
+
+/// 应用的平台, 对应蒲公英接口里的 buildType
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    Ios,
+    Android,
+}
+
+impl Platform {
+    pub fn parse(value: &str) -> Result<Platform> {
+        match value.to_ascii_lowercase().as_str() {
+            "ios" => Ok(Platform::Ios),
+            "android" => Ok(Platform::Android),
+            _ => Err(PgyerError::Validation(format!(
+                "type 只能是 ios 或 android: {}",
+                value
+            ))),
+        }
+    }
+
+    pub fn build_type(&self) -> &'static str {
+        match self {
+            Platform::Ios => "1",
+            Platform::Android => "2",
+        }
+    }
+}
+
+/// 未设置的条件不参与过滤
+#[derive(Debug, Clone, Default)]
+pub struct AppFilter {
+    /// 名称中包含的文字, 不区分大小写
+    pub name: Option<String>,
+    pub platform: Option<Platform>,
+    /// 最新一次构建的上传日期不早于该日期
+    pub since: Option<NaiveDate>,
+}
+
+impl AppFilter {
+    /// 日期格式为 yyyy-MM-dd
+    pub fn parse_date(value: &str) -> Result<NaiveDate> {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+            PgyerError::Validation(format!(
+                "日期格式不正确, 正确格式 yyyy-MM-dd (2001-02-01): {}",
+                value
+            ))
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.platform.is_none() && self.since.is_none()
+    }
+
+    pub fn matches(&self, app: &AppSummary) -> bool {
+        if let Some(name) = &self.name {
+            if !app.build_name.to_lowercase().contains(&name.to_lowercase()) {
+                return false;
+            }
+        }
+
+        if let Some(platform) = self.platform {
+            if app.build_type != platform.build_type() {
+                return false;
+            }
+        }
+
+        if let Some(since) = self.since {
+            // buildCreated 的格式为 2001-02-01 12:00:00
+            let created = app
+                .build_created
+                .get(..10)
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+            // 无法解析日期时不显示
+            if created.is_none_or(|created| created < since) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str, build_type: &str, created: &str) -> AppSummary {
+        AppSummary {
+            build_name: name.to_owned(),
+            build_type: build_type.to_owned(),
+            build_created: created.to_owned(),
+            ..AppSummary::default()
+        }
+    }
+
+    fn date(value: &str) -> NaiveDate {
+        AppFilter::parse_date(value).unwrap()
+    }
+
+    #[test]
+    fn empty_filter_matches_all() {
+        let filter = AppFilter::default();
+        assert!(filter.is_empty());
+        assert!(filter.matches(&app("", "", "")));
+    }
+
+    #[test]
+    fn match_name_ignoring_case() {
+        let filter = AppFilter {
+            name: Some("demo".to_owned()),
+            ..AppFilter::default()
+        };
+        assert!(filter.matches(&app("My Demo App", "1", "")));
+        assert!(filter.matches(&app("DEMO", "2", "")));
+        assert!(!filter.matches(&app("Other", "1", "")));
+    }
+
+    #[test]
+    fn match_platform() {
+        let filter = AppFilter {
+            platform: Some(Platform::parse("iOS").unwrap()),
+            ..AppFilter::default()
+        };
+        assert!(filter.matches(&app("Demo", "1", "")));
+        assert!(!filter.matches(&app("Demo", "2", "")));
+        assert!(Platform::parse("windows").is_err());
+    }
+
+    #[test]
+    fn match_since() {
+        let filter = AppFilter {
+            since: Some(date("2024-01-02")),
+            ..AppFilter::default()
+        };
+        assert!(filter.matches(&app("Demo", "1", "2024-01-02 00:00:00")));
+        assert!(filter.matches(&app("Demo", "1", "2024-02-01 12:00:00")));
+        assert!(!filter.matches(&app("Demo", "1", "2024-01-01 23:59:59")));
+        // 无法解析的日期不显示
+        assert!(!filter.matches(&app("Demo", "1", "")));
+        assert!(!filter.matches(&app("Demo", "1", "yesterday")));
+    }
+
+    #[test]
+    fn match_all_conditions() {
+        let filter = AppFilter {
+            name: Some("demo".to_owned()),
+            platform: Some(Platform::Android),
+            since: Some(date("2024-01-01")),
+        };
+        assert!(filter.matches(&app("Demo", "2", "2024-01-01 00:00:00")));
+        assert!(!filter.matches(&app("Demo", "1", "2024-01-01 00:00:00")));
+        assert!(!filter.matches(&app("Other", "2", "2024-01-01 00:00:00")));
+        assert!(AppFilter::parse_date("2024-02-30").is_err());
+    }
+}
@@ -1,573 +1,967 @@
+pub mod batch;
+pub mod client;
+pub mod config;
+pub mod credential;
+pub mod duplicate;
+pub mod error;
+pub mod filter;
+pub mod models;
+pub mod options;
+pub mod output;
+pub mod package;
+pub mod progress;
+pub mod retry;
+pub mod table;
+pub mod watch;
+
 pub mod app {
     use std::{
-        collections::HashMap,
-        env,
-        error::Error,
-        fs,
+        collections::BTreeMap,
+        env, fs,
+        io::{self, IsTerminal},
         path::Path,
-        process, thread,
+        sync::{Arc, Mutex},
         time::{Duration, Instant},
     };
 
     use chrono::Local;
-    use clap::{App, AppSettings, Arg, ArgMatches};
-    use reqwest::{
-        multipart::{self, Form},
-        Client,
+    use futures_util::TryStreamExt;
+    use reqwest::{multipart::Part, Body};
+    use serde_json::{json, Value};
+    use tokio_util::io::ReaderStream;
+
+    use crate::{
+        client::PgyerClient,
+        config::{is_secret, mask_secret, validate_api_key, Config, Profile, GLOBAL_KEYS},
+        credential::{self, resolve_api_key},
+        duplicate::{find_duplicate, DuplicateCheck},
+        error::{PgyerError, Result},
+        filter::AppFilter,
+        models::{AppListPage, AppSummary, BuildInfo, BuildListPage, CosToken},
+        options::{
+            ConfigCommand, DeleteOptions, DuplicatePolicy, InstallType, InstallWindow,
+            UploadOptions,
+        },
+        output::{self, print_json_result, print_result, status, warning},
+        package::{validate_package, PackageInfo},
+        progress::{FileProgress, Spinner, UploadProgress},
+        retry::RetryPolicy,
+        table::{self, sort_rows, TableOptions},
     };
-    use serde_json::{Result, Value};
     const MB: u64 = 1024 * 1024;
     const GB: u64 = MB * 1024;
-    const INSTALL_PASSWORD: &str = "2";
-    const INSTALL_AT_DATE_RANGE: &str = "1";
-    const PGYER_API_ENDPOINT: &str = "https://www.pgyer.com/apiv2/app/";
-    const VERSION_NUMBER: &str = "0.2.3";
+    // 上传时每次从磁盘读取的块大小
+    const UPLOAD_CHUNK_SIZE: usize = 4 * MB as usize;
+    // 等待服务端处理时轮询间隔的上限, 每次轮询后按倍数增长
+    const MAX_POLL_INTERVAL: Duration = Duration::from_secs(10);
+    const POLL_BACKOFF: f64 = 1.5;
+
+    /// 上传的结果
+    #[derive(Debug, Clone)]
+    pub enum UploadOutcome {
+        /// 服务端处理完成
+        Published(Box<BuildInfo>),
+        /// 没有等待服务端处理
+        Processing { build_key: String },
+        /// 蒲公英上已有版本号, 构建号和文件大小都相同的构建, 没有上传
+        Skipped(Box<BuildInfo>),
+    }
+
+    // 以表单的方式把安装包上传到 cos, 每次重试都会重新打开文件
+    //
+    // getCOSToken 返回的签名只能用于表单上传, cos 的分块上传需要对每个请求的方法和路径签名,
+    // 所以大文件也只能整个上传, 失败后从头重试
+    async fn post_to_cos(
+        client: &PgyerClient,
+        token_info: &CosToken,
+        file_path: &Path,
+        file_size: u64,
+        progress: &Arc<FileProgress>,
+    ) -> Result<()> {
+        let file_name = file_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        // 以流的方式从磁盘分块读取, 避免把整个安装包读进内存
+        let file = tokio::fs::File::open(file_path).await?;
+        progress.reset();
+        let stream_progress = progress.clone();
+        let stream = ReaderStream::with_capacity(file, UPLOAD_CHUNK_SIZE)
+            .inspect_ok(move |chunk| stream_progress.inc(chunk.len() as u64));
+        let body = Body::wrap_stream(stream);
+
+        let form = reqwest::multipart::Form::new()
+            .text("signature", token_info.params.signature.clone())
+            .text("x-cos-meta-file-name", file_name.clone())
+            .text(
+                "x-cos-security-token",
+                token_info.params.x_cos_security_token.clone(),
+            )
+            .text("key", token_info.key.clone())
+            .part(
+                "file",
+                Part::stream_with_length(body, file_size).file_name(file_name),
+            );
 
-    async fn upload_file(token_info: &Value) {
-        let matches = get_command_params();
+        let response = client
+            .http()
+            .post(&token_info.endpoint)
+            .multipart(form)
+            .send()
+            .await?;
+        let status = response.status();
 
-        let file_path = matches.value_of("file");
-        let file_name = Path::new(file_path.unwrap()).file_name();
+        if status != 204 {
+            return Err(PgyerError::Http {
+                status: status.as_u16(),
+                body: response.text().await.unwrap_or_default(),
+            });
+        }
+
+        Ok(())
+    }
+
+    // shared 为批量上传共用的进度, 为 None 时单独显示这个文件的进度
+    async fn upload_file(
+        client: &PgyerClient,
+        token_info: &CosToken,
+        options: &UploadOptions,
+        shared: Option<&Arc<UploadProgress>>,
+    ) -> Result<UploadOutcome> {
         // Retrieve the metadata of the file
-        let metadata = fs::metadata(file_path.unwrap()).expect("Failed to read metadata");
+        let metadata = fs::metadata(&options.file)?;
 
         // Extract the file size from the metadata
         let file_size = metadata.len();
 
         // 大于 2GB 不让传
         if file_size > (2 * GB) {
-            println!("当前文件大于 2GB, 无法上传");
-            process::exit(0);
+            return Err(PgyerError::Validation(
+                "当前文件大于 2GB, 无法上传".to_owned(),
+            ));
         }
 
         // Start measuring the run time
         let start_time = Instant::now();
 
         let current_time = Local::now();
-        println!("当前时间: {}", current_time);
-
-        let form = reqwest::multipart::Form::new()
-            .text(
-                "signature",
-                token_info["params"]["signature"]
-                    .as_str()
-                    .unwrap()
-                    .to_owned(),
-            )
-            .text(
-                "x-cos-meta-file-name",
-                file_name.unwrap().to_str().unwrap().to_owned(),
-            )
-            .text(
-                "x-cos-security-token",
-                token_info["params"]["x-cos-security-token"]
-                    .as_str()
-                    .unwrap()
-                    .to_owned(),
-            )
-            .text("key", token_info["key"].as_str().unwrap().to_owned())
-            .part(
-                "file",
-                reqwest::multipart::Part::bytes(
-                    std::fs::read(&file_path.unwrap()).expect("读取文件失败!"),
-                )
-                .file_name(file_name.unwrap().to_string_lossy().to_string()),
-            );
-
-        let client = reqwest::Client::builder().build().unwrap();
-        let request = client
-            .request(
-                reqwest::Method::POST,
-                token_info["endpoint"].as_str().unwrap(),
-            )
-            .multipart(form);
-
-        println!("上传中...");
+        status!("当前时间: {}", current_time);
+
+        let progress = match shared {
+            Some(progress) => progress.clone(),
+            None => Arc::new(UploadProgress::new(file_size)),
+        };
+        let file_progress = Arc::new(FileProgress::new(progress.clone()));
+        status!("上传中...");
+
+        let file_path = options.file.as_path();
+        let uploaded = client
+            .retry_policy()
+            .run("上传", || {
+                post_to_cos(client, token_info, file_path, file_size, &file_progress)
+            })
+            .await;
+        if let Err(err) = uploaded {
+            if shared.is_none() {
+                progress.abandon();
+            }
+            status!("上传失败!!!");
+            return Err(err);
+        }
 
-        let response = request.send().await.unwrap();
-        let status = response.status();
+        if shared.is_none() {
+            progress.finish();
+        }
+        let duration = start_time.elapsed().as_secs_f32();
+        status!("上传耗时: {:.2} 秒", duration); // Calculate the run time duration
 
-        if status != 204 {
-            println!("上传失败!!!");
-            println!("{}", response.text().await.unwrap());
-            process::exit(0);
+        if options.no_wait {
+            status!("上传完成, 服务端处理中, buildKey: {}", token_info.key);
+            return Ok(UploadOutcome::Processing {
+                build_key: token_info.key.clone(),
+            });
         }
 
-        let mut build_code = 1246;
-        let build_deal_code = vec![1246, 1247];
-        let error_code = 1216;
+        status!("上传完成, 服务端处理中...");
+        status!("当前时间: {}", Local::now());
 
-        let duration = start_time.elapsed().as_secs_f32();
-        println!("上传耗时: {:.2} 秒", duration); // Calculate the run time duration
+        let build_info = wait_for_build(
+            client,
+            &token_info.key,
+            options.poll_interval,
+            options.wait_timeout,
+        )
+        .await?;
+        status!("应用信息: ");
+        Ok(UploadOutcome::Published(Box::new(build_info)))
+    }
 
-        println!("上传完成, 服务端处理中...");
-        println!("当前时间: {}", Local::now());
-        println!("获取应用信息...");
+    /// 轮询 buildInfo 直到服务端处理完成, 每次轮询的间隔逐渐变长, 超过 `timeout` 仍未完成时返回超时错误
+    pub async fn wait_for_build(
+        client: &PgyerClient,
+        build_key: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<BuildInfo> {
+        let build_deal_code = [1246, 1247];
+        let error_code = 1216;
 
-        while build_code != 0 {
-            let build_info = get_build_info(token_info["key"].as_str().unwrap()).await;
-            build_code = build_info.get("code").unwrap().as_i64().unwrap();
-            thread::sleep(Duration::from_secs(1));
+        let deadline = Instant::now() + timeout;
+        let max_interval = (poll_interval * 10).max(MAX_POLL_INTERVAL);
+        let mut interval = poll_interval;
+        let timeout_error = || {
+            PgyerError::Timeout(format!(
+                "等待服务端处理超时 ({} 秒), buildKey: {}",
+                timeout.as_secs(),
+                build_key
+            ))
+        };
+        let mut spinner = Spinner::new("获取应用信息...");
+        loop {
+            // 单次查询 (包括重试) 也不能超过剩余的等待时间
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let build_info =
+                match tokio::time::timeout(remaining, client.build_info(build_key)).await {
+                    Ok(build_info) => build_info?,
+                    Err(_) => {
+                        spinner.finish();
+                        return Err(timeout_error());
+                    }
+                };
+            spinner.tick();
+
+            if build_deal_code.contains(&build_info.code) {
+                let now = Instant::now();
+                if now >= deadline {
+                    spinner.finish();
+                    return Err(timeout_error());
+                }
 
-            if build_deal_code.contains(&build_code) {
+                tokio::time::sleep(interval.min(deadline - now)).await;
+                interval = interval.mul_f64(POLL_BACKOFF).min(max_interval);
                 continue;
             }
 
-            if build_code == 0 {
-                println!("应用信息: ");
-                pretty_json(&build_info.get("data").unwrap());
-                process::exit(0);
+            spinner.finish();
+            if build_info.is_success() {
+                return Ok(build_info.data.unwrap_or_default());
             }
 
-            if build_code == error_code {
-                println!("服务端处理失败了!");
-                break;
+            if build_info.code == error_code {
+                return Err(PgyerError::Processing("服务端处理失败了!".to_owned()));
             }
-        }
-    }
-
-    pub fn check_params() {
-        let matches = get_command_params();
-        if let Some(api_key) = matches.value_of("api_key") {
-            set_api_key(api_key);
-            println!("api_key: {}", api_key);
-        }
 
-        if let Some(output_file) = matches.value_of("file") {
-            println!("file: {}", output_file);
+            return Err(build_info.into());
         }
+    }
 
-        let api_key = get_api_key();
-        if api_key.is_empty() {
-            println!("请先设置 api_key");
-            process::exit(0);
-        }
+    /// 上传安装包, 作为库使用时 `options` 中没有设置的项不会使用配置文件中的默认值
+    pub async fn upload(client: &PgyerClient, options: &UploadOptions) -> Result<UploadOutcome> {
+        upload_with_progress(client, options, None).await
     }
 
-    pub async fn upload() {
-        let matches = get_command_params();
+    pub(crate) async fn upload_with_progress(
+        client: &PgyerClient,
+        options: &UploadOptions,
+        progress: Option<&Arc<UploadProgress>>,
+    ) -> Result<UploadOutcome> {
+        let path = options.file.as_path();
+        status!("file: {}", path.display());
+        if fs::metadata(path).is_err() {
+            return Err(PgyerError::Validation("文件不存在!".to_owned()));
+        }
+        options.validate()?;
 
-        let file_path = matches.value_of("file");
-        let build_type;
-        if let Some(name) = file_path {
-            let name_str = name;
-            if fs::metadata(&name_str).is_err() {
-                println!("文件不存在!");
-                process::exit(0);
-            }
+        // 在申请上传凭证之前检查安装包内容
+        let build_type = validate_package(path)?.as_str();
+        let package_info = read_package_info(path);
 
-            let extension = Path::new(name).extension().and_then(|ext| ext.to_str());
+        check_proxy().await;
 
-            let build_deal_code = vec!["apk", "ipa"];
-            match extension {
-                Some(ext) => {
-                    build_type = ext;
-                    if !build_deal_code.contains(&ext.to_lowercase().as_str()) {
-                        println!("只支持ipa/apk");
-                        process::exit(0);
-                    }
+        if options.duplicate != DuplicatePolicy::Ignore {
+            let package_info = package_info?;
+            let file_size = fs::metadata(path)?.len();
+            match find_duplicate(client, &package_info, file_size).await? {
+                DuplicateCheck::SameVersion { build, .. }
+                    if options.duplicate == DuplicatePolicy::Fail =>
+                {
+                    return Err(PgyerError::Duplicate {
+                        build_key: build.build_key,
+                    });
                 }
-                None => {
-                    println!("文件格式不正确");
-                    process::exit(0);
+                DuplicateCheck::SameVersion {
+                    build,
+                    same_size: true,
+                } => {
+                    warning!(
+                        "蒲公英上已存在版本号, 构建号和文件大小都相同的构建, 跳过上传, buildKey: {}",
+                        build.build_key
+                    );
+                    return Ok(UploadOutcome::Skipped(build));
                 }
+                DuplicateCheck::SameVersion { build, .. } => {
+                    warning!(
+                        "警告: 蒲公英上已存在相同版本号和构建号的构建, 但文件大小不同, buildKey: {}",
+                        build.build_key
+                    );
+                }
+                DuplicateCheck::NotFound => {}
             }
-        } else {
-            println!("请携带文件参数来上传应用, -h 获取更多帮助");
-            process::exit(0);
         }
 
-        check_proxy().await;
+        let token_info = get_cos_token(client, build_type, options).await?;
+        check_endpoint(client, &token_info.endpoint).await?;
+        upload_file(client, &token_info, options, progress).await
+    }
 
-        let token_info = get_cos_token(&matches, build_type).await.unwrap();
-        check_endpoint(token_info["data"]["endpoint"].as_str().unwrap()).await;
-        upload_file(&token_info["data"]).await;
+    // 输出安装包信息, 解析失败时只给出提示, 由调用方决定是否继续上传
+    fn read_package_info(path: &Path) -> Result<PackageInfo> {
+        let info = PackageInfo::read(path);
+        match &info {
+            Ok(info) => {
+                status!("安装包信息:\n{}", info);
+                for warning in info.warnings() {
+                    warning!("警告: {}", warning);
+                }
+            }
+            Err(err) => warning!("无法读取安装包信息: {}", err),
+        }
+        info
     }
 
-    pub async fn check_proxy() {
-        let mut is_use_proxy = false;
+    /// 检查是否配置了代理, 返回 `{"httpProxy": bool, "httpsProxy": bool}`
+    pub async fn check_proxy() -> Value {
         // Check if the HTTP_PROXY or http_proxy environment variable is set
-        if env::var_os("HTTP_PROXY").is_some() || env::var_os("http_proxy").is_some() {
-            is_use_proxy = true;
-            println!("Proxy is configured for HTTP traffic.");
+        let http_proxy = env::var_os("HTTP_PROXY").is_some() || env::var_os("http_proxy").is_some();
+        if http_proxy {
+            status!("Proxy is configured for HTTP traffic.");
         }
 
         // Check if the HTTPS_PROXY or https_proxy environment variable is set
-        if env::var_os("HTTPS_PROXY").is_some() || env::var_os("https_proxy").is_some() {
-            is_use_proxy = true;
-            println!("Proxy is configured for HTTPS traffic.");
+        let https_proxy =
+            env::var_os("HTTPS_PROXY").is_some() || env::var_os("https_proxy").is_some();
+        if https_proxy {
+            status!("Proxy is configured for HTTPS traffic.");
         }
 
-        if is_use_proxy {
-            println!("您使用了代理, 可能导致上传失败, 请关闭代理或者使 pgyer.com 走直连通道!");
+        if http_proxy || https_proxy {
+            status!("您使用了代理, 可能导致上传失败, 请关闭代理或者使 pgyer.com 走直连通道!");
         }
+        json!({ "httpProxy": http_proxy, "httpsProxy": https_proxy })
     }
 
-    pub async fn check_endpoint(endpoint: &str) {
-        let client = Client::new();
-        let response = client.get(endpoint).send().await;
-        match response {
-            Ok(res) => {
-                if res.status().is_success() {
-                } else if res.status() == 403 {
-                    // println!("检测网络正常!");
-                } else {
-                    println!(
-                        "myqcloud.com Request failed with status code: {}",
-                        res.status()
-                    );
-                    process::exit(0)
-                }
-            }
-            Err(err) => {
-                println!("Request error: {:?}", err.source());
-                process::exit(0)
-            }
+    pub async fn check_endpoint(client: &PgyerClient, endpoint: &str) -> Result<()> {
+        let res = client.http().get(endpoint).send().await?;
+        // 未携带签名访问时 cos 会返回 403, 说明网络是通的
+        if res.status().is_success() || res.status() == 403 {
+            return Ok(());
         }
+
+        status!("myqcloud.com Request failed");
+        Err(PgyerError::Http {
+            status: res.status().as_u16(),
+            body: String::new(),
+        })
     }
 
-    fn get_app_cli() -> App<'static, 'static> {
-        return App::new("PGYER APP MANAGER")
-        .version(VERSION_NUMBER)
-        .author("PANG")
-        .about("PGYER APP MANAGER")
-        .arg(
-            Arg::with_name("channel")
-                .short("c")
-                .long("channel")
-                .value_name("STRING")
-                .help("build channel shortcut")
-                .takes_value(true),
-        )
-        .arg(Arg::with_name("check").long("check").help("check network"))
-        .arg(
-            Arg::with_name("description")
-                .short("d")
-                .long("description")
-                .value_name("STRING")
-                .help("build update description")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("installEndDate")
-                .short("e")
-                .long("installEndDate")
-                .value_name("STRING")
-                .help("build install start date, format: yyyy-MM-dd")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("file")
-                .short("f")
-                .long("file")
-                .value_name("FILE")
-                .help("Sets the upload file")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("installDate")
-                .short("i")
-                .long("installDate")
-                .value_name("NUMBER")
-                .help("build install date, 1=buildInstallStartDate~buildInstallEndDate, 2=forever")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("api_key")
-                .short("k")
-                .long("key")
-                .value_name("STRING")
-                .help("Sets the api key")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("list")
-                .short("l")
-                .long("list")
-                .help("list my apps"),
-        )
-        .arg(
-            Arg::with_name("page")
-                .long("page")
-                .value_name("NUMBER")
-                .help("page number")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("appKey")
-                .short("r")
-                .long("remove")
-                .value_name("STRING")
-                .help("app key that you want to delete")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("buildKey")
-                .long("removeBuild")
-                .value_name("STRING")
-                .help("build key that you want to delete")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("installStartDate")
-                .short("s")
-                .long("installStartDate")
-                .value_name("STRING")
-                .help("build install end date, format: yyyy-MM-dd")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("password")
-                .short("p")
-                .long("password")
-                .value_name("STRING")
-                .help("build password, required if installType=2")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("installType")
-                .short("t")
-                .long("installType")
-                .value_name("NUMBER")
-                .help("build install type, 1=public, 2=password, 3=invite")
-                .takes_value(true),
-        )
-        .arg(
-          Arg::with_name("info")
-              .long("info")
-              .value_name("STRING")
-              .help("get build info with build key")
-              .takes_value(true),
-      ).setting(AppSettings::ArgRequiredElseHelp);
+    /// 保存 api_key 到 `profile`, 没有指定时使用默认的 profile
+    pub fn set_api_key(profile: Option<&str>, api_key: &str) -> Result<()> {
+        let mut config = Config::load()?;
+        let name = config.active_profile_name(profile);
+        store_api_key(&mut config, &name, api_key)?;
+        status!("api_key: {}", mask_secret(api_key));
+        Ok(())
     }
 
-    pub fn get_command_params() -> ArgMatches<'static> {
-        let app = get_app_cli();
-        return app.get_matches();
+    /// 只在本次运行中使用 api_key, 不保存
+    pub fn use_api_key(profile: Option<&str>, api_key: &str) -> Result<()> {
+        validate_api_key(api_key)?;
+        let name = Config::load()?.active_profile_name(profile);
+        API_KEYS.lock().unwrap().insert(name, api_key.to_owned());
+        Ok(())
     }
 
-    fn set_api_key(api_key: &str) {
-        if api_key.len() != 32 {
-            println!("api_key is invalid");
-            process::exit(0);
+    // 保存到配置的存储后端, 加密保存时顺便删除配置文件里的明文
+    fn store_api_key(config: &mut Config, name: &str, api_key: &str) -> Result<()> {
+        validate_api_key(api_key)?;
+        credential::from_config(config)?.set(name, api_key)?;
+        if !credential::is_plaintext(config) {
+            let plaintext = config
+                .profiles
+                .get_mut(name)
+                .and_then(|profile| profile.api_key.take());
+            if plaintext.is_some() {
+                config.save()?;
+            }
         }
+        Ok(())
+    }
+
+    // 加密保存时每次读取都需要口令, 同一次运行中每个 profile 只读取一次
+    static API_KEYS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
 
-        let db: sled::Db = sled::open("my_db").unwrap();
-        db.insert("_api_key", api_key).unwrap();
+    fn get_api_key(profile: Option<&str>) -> Result<String> {
+        let config = Config::load()?;
+        let name = config.active_profile_name(profile);
+        if let Some(api_key) = API_KEYS.lock().unwrap().get(&name) {
+            return Ok(api_key.clone());
+        }
+
+        let store = credential::from_config(&config)?;
+        let api_key = match resolve_api_key(store.as_ref(), &name)? {
+            Some(api_key) => api_key,
+            None => match config
+                .profile(&name)
+                .and_then(|profile| profile.api_key.clone())
+            {
+                Some(api_key) => {
+                    status!(
+                        "提示: api_key 以明文保存在配置文件中, 运行 config set api_key <KEY> 改为加密保存"
+                    );
+                    api_key
+                }
+                None => String::new(),
+            },
+        };
+        API_KEYS.lock().unwrap().insert(name, api_key.clone());
+        Ok(api_key)
     }
 
-    fn get_api_key() -> String {
-        let db: sled::Db = sled::open("my_db").unwrap();
-        if let Ok(Some(value)) = db.get("_api_key") {
-            String::from_utf8(value.to_vec()).expect("_api_key not init")
-        } else {
-            if let Ok(pgyer_api_key) = env::var("PGYER_API_KEY") {
-                return pgyer_api_key;
-            }
-            return String::from("");
+    /// 配置文件中的 profile, 没有指定时使用默认的 profile, 配置里没有时返回空的 profile
+    pub fn load_profile(profile: Option<&str>) -> Result<Profile> {
+        let config = Config::load()?;
+        let name = config.active_profile_name(profile);
+        Ok(config.profile(&name).cloned().unwrap_or_default())
+    }
+
+    /// 使用本地保存的 api_key 创建客户端
+    pub fn new_client(profile: Option<&str>, retry: RetryPolicy) -> Result<PgyerClient> {
+        let api_key = get_api_key(profile)?;
+        if api_key.is_empty() {
+            return Err(PgyerError::Validation("请先设置 api_key".to_owned()));
         }
+        PgyerClient::builder(&api_key).retry(retry).build()
     }
 
-    pub async fn get_cos_token(matches: &ArgMatches<'_>, build_type: &str) -> Result<Value> {
-        let api_key = get_api_key();
+    pub async fn get_cos_token(
+        client: &PgyerClient,
+        build_type: &str,
+        options: &UploadOptions,
+    ) -> Result<CosToken> {
+        let (start, end) = match options.install_window {
+            InstallWindow::Range(start, end) => (start.to_string(), end.to_string()),
+            InstallWindow::Forever => (String::new(), String::new()),
+        };
+        let install_type = options.install_type.as_ref();
         let pairs: Vec<(&str, &str)> = vec![
-            ("_api_key", &api_key),
-            ("buildType", build_type),
             (
                 "buildChannelShortcut",
-                matches.value_of("channel").unwrap_or(""),
-            ),
-            (
-                "buildInstallEndDate",
-                matches.value_of("installEndDate").unwrap_or(""),
-            ),
-            (
-                "buildInstallStartDate",
-                matches.value_of("installStartDate").unwrap_or(""),
+                options.channel.as_deref().unwrap_or(""),
             ),
+            ("buildInstallEndDate", &end),
+            ("buildInstallStartDate", &start),
+            ("buildInstallDate", options.install_window.code()),
             (
-                "buildInstallDate",
-                matches.value_of("installDate").unwrap_or(""),
+                "buildDescription",
+                options.description.as_deref().unwrap_or(""),
             ),
             (
-                "buildDescription",
-                matches.value_of("description").unwrap_or(""),
+                "buildPassword",
+                install_type.and_then(InstallType::password).unwrap_or(""),
             ),
-            ("buildPassword", matches.value_of("password").unwrap_or("")),
             (
                 "buildInstallType",
-                matches.value_of("installType").unwrap_or(""),
+                install_type.map(InstallType::code).unwrap_or(""),
             ),
         ];
 
-        if matches.value_of("installType") == Some(INSTALL_PASSWORD)
-            && matches.value_of("password").is_none()
-        {
-            println!("密码安装方式需要传递 password 参数");
-            process::exit(0);
-        }
+        client.get_cos_token(build_type, &pairs).await
+    }
 
-        if matches.value_of("installDate") == Some(INSTALL_AT_DATE_RANGE)
-            && (matches.value_of("installStartDate").is_none()
-                || matches.value_of("installEndDate").is_none())
-        {
-            println!("需要传递安装时间参数");
-            process::exit(0);
+    pub async fn delete_app(
+        client: &PgyerClient,
+        app_key: &str,
+        options: DeleteOptions,
+    ) -> Result<()> {
+        // 应用信息取自最新的一次构建
+        let builds = client.builds(app_key, 1).await?;
+        let latest = builds.list.first();
+        let app_name = match latest {
+            Some(build) => {
+                status!(
+                    "将要删除应用 {} {} ({}), 以及全部 {} 个构建和下载记录, appKey: {}",
+                    build.build_name,
+                    build.build_version,
+                    build.build_identifier,
+                    builds.count,
+                    app_key
+                );
+                build.build_name.clone()
+            }
+            None => {
+                status!("将要删除应用, appKey: {}", app_key);
+                app_key.to_owned()
+            }
+        };
+        let mut result = json!({
+            "appKey": app_key,
+            "buildName": latest.map(|build| build.build_name.as_str()),
+            "buildVersion": latest.map(|build| build.build_version.as_str()),
+            "buildIdentifier": latest.map(|build| build.build_identifier.as_str()),
+            "buildCount": builds.count,
+            "dryRun": options.dry_run,
+            "deleted": false,
+        });
+        if options.dry_run {
+            status!("--dry-run, 没有删除");
+            print_json_result(&result);
+            return Ok(());
         }
+        // 删除应用需要输入应用名称确认
+        confirm_delete(options, Some(&app_name))?;
+
+        status!("删除中...");
+        client.delete_app(app_key).await?;
+
+        status!("删除成功");
+        result["deleted"] = json!(true);
+        print_json_result(&result);
+        Ok(())
+    }
 
-        let install_end_date = matches.value_of("installEndDate").unwrap_or("");
-        let install_start_date = matches.value_of("installStartDate").unwrap_or("");
-        if (install_start_date.len() > 0 && install_start_date.len() != 10)
-            || (install_end_date.len() > 0 && install_end_date.len() != 10)
-        {
-            println!("时间参数不正确, 正确格式 yy-MM-DD (2001-02-01)");
-            process::exit(0);
+    pub async fn delete_build(
+        client: &PgyerClient,
+        build_key: &str,
+        options: DeleteOptions,
+    ) -> Result<()> {
+        let build_info = client.build_info(build_key).await?;
+        if !build_info.is_success() {
+            return Err(build_info.into());
+        }
+        let build = build_info.data.unwrap_or_default();
+        status!(
+            "将要删除构建 {} {} (build {}), 上传于 {}, buildKey: {}",
+            build.build_name,
+            build.build_version,
+            build.build_version_no,
+            build.build_created,
+            build_key
+        );
+        let mut result = json!({
+            "buildKey": build_key,
+            "build": build,
+            "dryRun": options.dry_run,
+            "deleted": false,
+        });
+        if options.dry_run {
+            status!("--dry-run, 没有删除");
+            print_json_result(&result);
+            return Ok(());
         }
+        confirm_delete(options, None)?;
 
-        let url = PGYER_API_ENDPOINT.to_owned() + "getCOSToken";
-        let res = request(pairs, &url).await.unwrap();
+        status!("删除中...");
+        client.delete_build(build_key).await?;
 
-        return Ok(res);
+        status!("删除成功");
+        result["deleted"] = json!(true);
+        print_json_result(&result);
+        Ok(())
     }
 
-    pub async fn delete_app(matches: &ArgMatches<'_>) {
-        let api_key = get_api_key();
-        if matches.value_of("appKey").is_none() {
-            println!("需要 appKey 参数");
-            process::exit(0);
+    // 删除前确认, 指定了 expected 时需要输入相同的内容, 非交互模式下需要 --yes
+    fn confirm_delete(options: DeleteOptions, expected: Option<&str>) -> Result<()> {
+        if options.yes {
+            return Ok(());
+        }
+        if !io::stdin().is_terminal() {
+            return Err(PgyerError::Validation(
+                "非交互模式下删除需要 --yes 参数确认".to_owned(),
+            ));
         }
 
-        let pairs: Vec<(&str, &str)> = vec![
-            ("_api_key", &api_key),
-            ("appKey", matches.value_of("appKey").unwrap_or("")),
-        ];
+        match expected {
+            Some(expected) => output::prompt(&format!("请输入 {} 确认删除: ", expected))?,
+            None => output::prompt("确认删除? [y/N] ")?,
+        }
+        let mut input = String::new();
+        io::stdin().read_line(&mut input)?;
+        let input = input.trim();
+
+        let confirmed = match expected {
+            Some(expected) => input == expected,
+            None => input.eq_ignore_ascii_case("y") || input.eq_ignore_ascii_case("yes"),
+        };
+        if !confirmed {
+            return Err(PgyerError::Validation("已取消删除".to_owned()));
+        }
+        Ok(())
+    }
 
-        println!("删除中...");
-        let url = PGYER_API_ENDPOINT.to_owned() + "deleteApp";
-        let res = request(pairs, &url).await.unwrap();
-        let build_code = res.get("code").unwrap();
-        if build_code != 0 {
-            println!("{}", res.get("message").unwrap());
-            process::exit(0);
+    /// 文本模式下输出表格, json 模式下输出完整的返回内容
+    ///
+    /// `page` 为 None 时请求所有页, 过滤条件在本地对取到的应用生效
+    pub async fn get_app_list(
+        client: &PgyerClient,
+        page: Option<u32>,
+        filter: &AppFilter,
+        table: &TableOptions<AppSummary>,
+    ) -> Result<()> {
+        let mut res = match page {
+            Some(page) => client.list_my(page).await?,
+            None => {
+                let list: Vec<AppSummary> = client.apps().try_collect().await?;
+                AppListPage {
+                    count: list.len() as u64,
+                    page_count: 1,
+                    list,
+                }
+            }
+        };
+        let total = res.list.len();
+        res.list.retain(|app| filter.matches(app));
+        if let Some(sort) = table.sort {
+            sort_rows(&mut res.list, sort);
         }
 
-        println!("删除成功");
-        process::exit(0);
+        if output::is_json() {
+            if page.is_none() {
+                res.count = res.list.len() as u64;
+            }
+            print_result(&res);
+            return Ok(());
+        }
+        println!(
+            "{}",
+            table::render(&res.list, &table.columns, table::terminal_width())
+        );
+        match page {
+            Some(page) => println!("第 {}/{} 页, 共 {} 个应用", page, res.page_count, res.count),
+            None => println!("共 {} 个应用", total),
+        }
+        if !filter.is_empty() {
+            println!("符合条件的 {} 个", res.list.len());
+        }
+        Ok(())
     }
 
-    pub async fn delete_build(matches: &ArgMatches<'_>) {
-        let api_key = get_api_key();
-        if matches.value_of("buildKey").is_none() {
-            println!("需要 buildKey 参数");
-            process::exit(0);
+    /// 列出应用的所有历史构建, 自动请求所有页
+    pub async fn get_build_list(
+        client: &PgyerClient,
+        app_key: &str,
+        table: &TableOptions<BuildInfo>,
+    ) -> Result<()> {
+        let mut list: Vec<BuildInfo> = client.app_builds(app_key).try_collect().await?;
+        if let Some(sort) = table.sort {
+            sort_rows(&mut list, sort);
         }
 
-        let pairs: Vec<(&str, &str)> = vec![
-            ("_api_key", &api_key),
-            ("buildKey", matches.value_of("buildKey").unwrap_or("")),
-        ];
-
-        println!("删除中...");
-        let url = PGYER_API_ENDPOINT.to_owned() + "buildDelete";
-        let res = request(pairs, &url).await.unwrap();
-        let build_code = res.get("code").unwrap();
-        if build_code != 0 {
-            println!("{}", res.get("message").unwrap());
-            process::exit(0);
+        if output::is_json() {
+            print_result(&BuildListPage {
+                count: list.len() as u64,
+                page_count: 1,
+                list,
+            });
+            return Ok(());
         }
+        println!(
+            "{}",
+            table::render(&list, &table.columns, table::terminal_width())
+        );
+        println!("共 {} 个构建", list.len());
+        Ok(())
+    }
 
-        println!("删除成功");
-        process::exit(0);
+    /// config 子命令, 不需要 api_key
+    pub fn config_command(profile: Option<&str>, command: &ConfigCommand) -> Result<()> {
+        let result = run_config_command(&mut Config::load()?, profile, command)?;
+        print_json_result(&result);
+        Ok(())
     }
 
-    pub async fn get_app_list(page: &str) {
-        let api_key = get_api_key();
+    // 修改保存到 config 读取时的文件, 返回 --output json 时输出的内容
+    fn run_config_command(
+        config: &mut Config,
+        profile: Option<&str>,
+        command: &ConfigCommand,
+    ) -> Result<Value> {
+        let name = config.active_profile_name(profile);
+        let show_secrets = matches!(
+            command,
+            ConfigCommand::Get {
+                show_secrets: true,
+                ..
+            } | ConfigCommand::List { show_secrets: true }
+        );
+        let display = |key: &str, value: &str| {
+            if is_secret(key) && !show_secrets {
+                mask_secret(value)
+            } else {
+                value.to_owned()
+            }
+        };
+
+        let result = match command {
+            ConfigCommand::Get { key, .. } => {
+                let key = key.as_str();
+                let profile = config.profile(&name).cloned().unwrap_or_default();
+                let value = if GLOBAL_KEYS.contains(&key) {
+                    config.option(key)?
+                } else if key == "api_key" {
+                    match credential::from_config(config)?.get(&name)? {
+                        Some(api_key) => Some(api_key),
+                        None => profile.api_key.clone(),
+                    }
+                } else {
+                    profile.get(key)?.map(str::to_owned)
+                };
+                let value = value.ok_or_else(|| {
+                    PgyerError::Config(format!("profile {} 中没有设置 {}", name, key))
+                })?;
+                let value = display(key, &value);
+                status!("{}", value);
+                json!({ "profile": name, "key": key, "value": value })
+            }
+            ConfigCommand::Set { key, value } => {
+                let (key, value) = (key.as_str(), value.as_str());
+                if GLOBAL_KEYS.contains(&key) {
+                    config.set_option(key, value)?;
+                    config.save()?;
+                    status!("{} = {}", key, value);
+                    return Ok(json!({ "key": key, "value": value }));
+                }
 
-        let pairs: Vec<(&str, &str)> = vec![("_api_key", &api_key), ("page", page)];
+                if key == "api_key" {
+                    store_api_key(config, &name, value)?;
+                } else {
+                    config.profile_mut(&name).set(key, value)?;
+                    config.save()?;
+                }
+                let value = display(key, value);
+                status!("[{}] {} = {}", name, key, value);
+                json!({ "profile": name, "key": key, "value": value })
+            }
+            ConfigCommand::Unset { key } => {
+                let key = key.as_str();
+                if GLOBAL_KEYS.contains(&key) {
+                    let removed = config.unset_option(key)?;
+                    if removed {
+                        config.save()?;
+                    }
+                    status!("已恢复 {} 的默认值", key);
+                    return Ok(json!({ "key": key, "removed": removed }));
+                }
 
-        let url = PGYER_API_ENDPOINT.to_owned() + "listMy";
-        let res = request(pairs, &url).await.unwrap();
-        let build_code = res.get("code").unwrap().as_i64().unwrap() as i32;
-        if build_code != 0 {
-            println!("{}", res.get("message").unwrap());
-            process::exit(0);
-        }
+                let mut removed = match config.profiles.get_mut(&name) {
+                    Some(profile) => profile.unset(key)?.is_some(),
+                    // 校验配置项名称
+                    None => Profile::default().get(key).map(|_| false)?,
+                };
+                if removed {
+                    config.save()?;
+                }
+                if key == "api_key" && !credential::is_plaintext(config) {
+                    removed |= credential::from_config(config)?.delete(&name)?;
+                }
+                if removed {
+                    status!("[{}] 已删除 {}", name, key);
+                } else {
+                    status!("[{}] 没有设置 {}", name, key);
+                }
+                json!({ "profile": name, "key": key, "removed": removed })
+            }
+            ConfigCommand::List { .. } => {
+                let mut options = BTreeMap::new();
+                for key in GLOBAL_KEYS {
+                    if let Some(value) = config.option(key)? {
+                        status!("{} = {}", key, value);
+                        options.insert(key, value);
+                    }
+                }
+                status!("[{}]", name);
+                let mut values = BTreeMap::new();
+                if let Some(profile) = config.profile(&name) {
+                    for (key, value) in profile.entries() {
+                        let value = display(key, value);
+                        status!("{} = {}", key, value);
+                        values.insert(key, value);
+                    }
+                }
+                json!({ "options": options, "profile": name, "values": values })
+            }
+            ConfigCommand::Path => {
+                let path = config.file()?;
+                status!("{}", path.display());
+                json!({ "path": path })
+            }
+            ConfigCommand::Profiles => {
+                let default_name = config.active_profile_name(None);
+                for profile in config.profiles.keys() {
+                    let marker = if *profile == default_name { "*" } else { " " };
+                    status!("{} {}", marker, profile);
+                }
+                json!({
+                    "default": default_name,
+                    "profiles": config.profiles.keys().collect::<Vec<_>>(),
+                })
+            }
+            ConfigCommand::Use { name: profile } => {
+                config.use_profile(profile)?;
+                config.save()?;
+                status!("默认 profile 已切换为 {}", profile);
+                json!({ "default": profile })
+            }
+        };
+        Ok(result)
+    }
 
-        pretty_json(res.get("data").unwrap());
+    pub async fn print_build_info(client: &PgyerClient, build_key: &str) -> Result<()> {
+        let build_info = client.build_info(build_key).await?;
+        if !build_info.is_success() {
+            return Err(build_info.into());
+        }
 
-        process::exit(0);
+        print_result(&build_info.data);
+        Ok(())
     }
 
-    fn pretty_json(str: &Value) {
-        let formatted_json = serde_json::to_string_pretty(str);
-        println!("{}", formatted_json.unwrap());
-    }
+    #[cfg(test)]
+    mod tests {
+        use std::path::PathBuf;
 
-    pub async fn get_build_info(build_key: &str) -> Value {
-        let api_key = get_api_key();
-        let pairs: Vec<(&str, &str)> = vec![("_api_key", &api_key), ("buildKey", build_key)];
-        let url = format!(
-            "{}buildInfo?_api_key={}&buildKey={}",
-            PGYER_API_ENDPOINT, api_key, build_key
-        );
-        let res = request(pairs, &url).await.unwrap();
-        return res;
-    }
+        use tempfile::TempDir;
 
-    pub async fn print_build_info(build_key: &str) {
-        let build_info = get_build_info(build_key).await;
-        pretty_json(build_info.get("data").unwrap());
-    }
+        use super::*;
+
+        const API_KEY: &str = "0123456789abcdef0123456789abcdef";
 
-    fn create_form(form_fields: HashMap<&str, &str>) -> Form {
-        let mut multipart_form = multipart::Form::new();
-        for (key, value) in form_fields {
-            multipart_form = multipart_form.text(key.to_string(), value.to_string());
+        // 临时目录中的配置文件, 加密口令保存在 key_file 中
+        fn temp_config() -> (TempDir, PathBuf) {
+            let dir = TempDir::new().unwrap();
+            let path = dir.path().join("config.toml");
+            let key_file = dir.path().join("key");
+            fs::write(&key_file, "passphrase\n").unwrap();
+            let mut config = Config::load_from(&path).unwrap();
+            config
+                .set_option("key_file", &key_file.display().to_string())
+                .unwrap();
+            config.save().unwrap();
+            (dir, path)
         }
-        multipart_form
-    }
 
-    async fn request(pairs: Vec<(&str, &str)>, url: &str) -> Result<Value> {
-        // Create a HashMap from the predefined pairs
-        let form_fields: HashMap<_, _> = pairs.into_iter().collect();
-
-        let form = create_form(form_fields);
-        let client = reqwest::Client::builder().build().unwrap();
-        let request = client.request(reqwest::Method::POST, url).multipart(form);
-
-        let response = request.send().await;
-        match response {
-            Ok(res) => {
-                match res.status() {
-                    reqwest::StatusCode::OK => {
-                        // Handle a successful response (status code 200)
-                        // let body = res.text().await.unwrap();
-                        // println!("Response body: {}", body);
-                        let body = res.text().await.unwrap();
-                        let person: Value = serde_json::from_str(body.as_str()).unwrap();
-                        return Ok(person);
-                    }
-                    reqwest::StatusCode::NO_CONTENT => {
-                        // Handle a "Not Found" response (status code 404)
-                        return Ok(().into());
-                    }
-                    reqwest::StatusCode::NOT_FOUND => {
-                        // Handle a "Not Found" response (status code 404)
-                        println!("404: Page not found");
-                        process::exit(0)
-                    }
-                    _ => {
-                        // Handle other response statuses
-                        println!("Received response with status: {}", res.status());
-                        process::exit(0)
-                    }
-                }
+        fn run(path: &Path, profile: Option<&str>, command: ConfigCommand) -> Result<Value> {
+            run_config_command(&mut Config::load_from(path)?, profile, &command)
+        }
+
+        fn set(key: &str, value: &str) -> ConfigCommand {
+            ConfigCommand::Set {
+                key: key.to_owned(),
+                value: value.to_owned(),
             }
-            Err(err) => {
-                // Handle a request error
-                println!("Request error: {}", err);
-                process::exit(0)
+        }
+
+        fn get(key: &str, show_secrets: bool) -> ConfigCommand {
+            ConfigCommand::Get {
+                key: key.to_owned(),
+                show_secrets,
             }
         }
+
+        #[test]
+        fn set_get_and_unset() {
+            let (_dir, path) = temp_config();
+            run(&path, None, set("channel", "beta")).unwrap();
+            let result = run(&path, None, get("channel", false)).unwrap();
+            assert_eq!(result["profile"], "default");
+            assert_eq!(result["value"], "beta");
+
+            let result = run(
+                &path,
+                None,
+                ConfigCommand::Unset {
+                    key: "channel".to_owned(),
+                },
+            )
+            .unwrap();
+            assert_eq!(result["removed"], true);
+            assert!(run(&path, None, get("channel", false)).is_err());
+            assert!(run(&path, None, set("unknown", "value")).is_err());
+        }
+
+        #[test]
+        fn mask_secrets_unless_requested() {
+            let (_dir, path) = temp_config();
+            run(&path, None, set("password", "secret")).unwrap();
+            let result = run(&path, None, set("api_key", API_KEY)).unwrap();
+            assert_eq!(result["value"], "0123********cdef");
+
+            // api_key 加密保存, 不会写入配置文件
+            let content = fs::read_to_string(&path).unwrap();
+            assert!(!content.contains(API_KEY));
+            let result = run(&path, None, get("api_key", false)).unwrap();
+            assert_eq!(result["value"], "0123********cdef");
+            let result = run(&path, None, get("api_key", true)).unwrap();
+            assert_eq!(result["value"], API_KEY);
+
+            let result = run(
+                &path,
+                None,
+                ConfigCommand::List {
+                    show_secrets: false,
+                },
+            )
+            .unwrap();
+            assert_eq!(result["values"]["password"], "********");
+            let result = run(&path, None, ConfigCommand::List { show_secrets: true }).unwrap();
+            assert_eq!(result["values"]["password"], "secret");
+            assert_eq!(result["options"]["credential_store"], "encrypted");
+
+            let result = run(
+                &path,
+                None,
+                ConfigCommand::Unset {
+                    key: "api_key".to_owned(),
+                },
+            )
+            .unwrap();
+            assert_eq!(result["removed"], true);
+            assert!(run(&path, None, get("api_key", true)).is_err());
+        }
+
+        #[test]
+        fn switch_profiles() {
+            let (_dir, path) = temp_config();
+            assert!(run(
+                &path,
+                None,
+                ConfigCommand::Use {
+                    name: "beta".to_owned()
+                }
+            )
+            .is_err());
+
+            run(&path, Some("beta"), set("channel", "beta")).unwrap();
+            run(&path, None, set("channel", "release")).unwrap();
+            run(
+                &path,
+                None,
+                ConfigCommand::Use {
+                    name: "beta".to_owned(),
+                },
+            )
+            .unwrap();
+
+            let result = run(&path, None, ConfigCommand::Profiles).unwrap();
+            assert_eq!(result["default"], "beta");
+            assert_eq!(result["profiles"], json!(["beta", "default"]));
+            let result = run(&path, None, get("channel", false)).unwrap();
+            assert_eq!(result["value"], "beta");
+            let result = run(&path, Some("default"), get("channel", false)).unwrap();
+            assert_eq!(result["value"], "release");
+
+            let result = run(&path, None, ConfigCommand::Path).unwrap();
+            assert_eq!(result["path"], json!(path));
+        }
     }
 }
@@ -1,29 +1,218 @@
-use pgyer_uploader::app;
+mod cli;
+
+use std::process;
+
+use clap::ArgMatches;
+use pgyer_uploader::{
+    app::{self, UploadOutcome},
+    batch,
+    client::PgyerClient,
+    error::PgyerError,
+    output::{self, OutputFormat},
+    table::{app_columns, build_columns, DEFAULT_APP_COLUMNS, DEFAULT_BUILD_COLUMNS},
+    watch::{self, UploadHistory},
+};
+use serde_json::json;
+
 #[tokio::main]
 async fn main() {
-    app::check_params();
-    let matches = app::get_command_params();
-    if matches.value_of("file").is_some() {
-        app::upload().await;
+    let matches = cli::get_command_params();
+    let format = cli::action_params(&matches)
+        .value_of("output")
+        .and_then(OutputFormat::parse)
+        .unwrap_or(OutputFormat::Text);
+    output::set_format(format);
+
+    if let Err(err) = run(&matches).await {
+        let code = err.exit_code();
+        if output::is_json() {
+            // 批量上传的汇总里已经包含每个文件的错误
+            if !matches!(err, PgyerError::Batch { .. }) {
+                output::print_result(&output::error_document(&err, code));
+            }
+        } else {
+            eprintln!("{}", err);
+        }
+        process::exit(code);
     }
+}
 
-    if matches.is_present("check") {
+async fn run(matches: &ArgMatches<'_>) -> Result<(), PgyerError> {
+    match matches.subcommand() {
+        ("config", Some(matches)) => match cli::config_command(matches) {
+            Some(command) => {
+                app::config_command(cli::profile_name(cli::action_params(matches)), &command)
+            }
+            None => Ok(()),
+        },
+        ("check", Some(_)) => {
+            output::print_json_result(&app::check_proxy().await);
+            Ok(())
+        }
+        ("upload", Some(matches)) => {
+            let client = new_client(matches)?;
+            upload(&client, matches).await
+        }
+        ("apps", Some(matches)) => match matches.subcommand() {
+            ("list", Some(matches)) => {
+                let client = new_client(matches)?;
+                get_app_list(&client, matches).await
+            }
+            ("delete", Some(matches)) => {
+                let client = new_client(matches)?;
+                let app_key = matches.value_of("appKey").unwrap_or_default();
+                app::delete_app(&client, app_key, cli::delete_options(matches)).await
+            }
+            _ => Ok(()),
+        },
+        ("builds", Some(matches)) => match matches.subcommand() {
+            ("list", Some(matches)) => {
+                let client = new_client(matches)?;
+                let app_key = matches.value_of("appKey").unwrap_or_default();
+                let table = cli::table_options(matches, build_columns(), DEFAULT_BUILD_COLUMNS)?;
+                app::get_build_list(&client, app_key, &table).await
+            }
+            ("info", Some(matches)) => {
+                let client = new_client(matches)?;
+                let build_key = matches.value_of("buildKey").unwrap_or_default();
+                app::print_build_info(&client, build_key).await
+            }
+            ("delete", Some(matches)) => {
+                let client = new_client(matches)?;
+                let build_key = matches.value_of("buildKey").unwrap_or_default();
+                app::delete_build(&client, build_key, cli::delete_options(matches)).await
+            }
+            _ => Ok(()),
+        },
+        _ => run_legacy(matches).await,
+    }
+}
+
+// 按 --profile 和重试参数创建客户端
+fn new_client(matches: &ArgMatches<'_>) -> Result<PgyerClient, PgyerError> {
+    app::new_client(cli::profile_name(matches), cli::retry_policy(matches)?)
+}
+
+// 旧版本的 -k 参数
+fn save_api_key(matches: &ArgMatches<'_>) -> Result<(), PgyerError> {
+    let profile = cli::profile_name(matches);
+    if let Some(api_key) = matches.value_of("api_key") {
+        // 非交互模式下没有口令时无法加密保存, 不影响旧脚本的执行
+        if let Err(err) = app::set_api_key(profile, api_key) {
+            if matches!(err, PgyerError::Validation(_)) {
+                return Err(err);
+            }
+            eprintln!("警告: api_key 没有保存, 只在本次运行中使用: {}", err);
+            app::use_api_key(profile, api_key)?;
+        }
+    }
+    Ok(())
+}
+
+// 命令行没有指定的上传参数使用 profile 中的默认值, 多个文件时批量上传, 指定 --watch 时监控目录
+async fn upload(client: &PgyerClient, matches: &ArgMatches<'_>) -> Result<(), PgyerError> {
+    let profile = app::load_profile(cli::profile_name(matches))?;
+    if let Some(options) = cli::watch_options(matches)? {
+        let mut history = UploadHistory::load()?;
+        return watch::watch(client, &options, &mut history, |file| {
+            cli::upload_options(matches, &profile, file)
+        })
+        .await;
+    }
+
+    let files = cli::upload_files(matches)?;
+    let parallel = cli::parallel(matches)?;
+    if files.len() > 1 {
+        let options = files
+            .iter()
+            .map(|file| cli::upload_options(matches, &profile, file))
+            .collect::<Result<Vec<_>, _>>()?;
         app::check_proxy().await;
+        let results = batch::upload_all(client, options, parallel).await;
+        return batch::report(&results);
+    }
+
+    let options = cli::upload_options(matches, &profile, &files[0])?;
+    match app::upload(client, &options).await? {
+        UploadOutcome::Processing { build_key } => {
+            output::print_json_result(&json!({ "buildKey": build_key }));
+        }
+        UploadOutcome::Published(build) | UploadOutcome::Skipped(build) => {
+            output::print_result(&build);
+        }
+    }
+    Ok(())
+}
+
+async fn get_app_list(client: &PgyerClient, matches: &ArgMatches<'_>) -> Result<(), PgyerError> {
+    let page = cli::page(matches)?;
+    let filter = cli::app_filter(matches)?;
+    let table = cli::table_options(matches, app_columns(), DEFAULT_APP_COLUMNS)?;
+    app::get_app_list(client, page, &filter, &table).await
+}
+
+// 旧版本不带子命令的参数
+const LEGACY_ACTIONS: [(&str, &str); 7] = [
+    ("api_key", "config set api_key <KEY>"),
+    ("file", "upload <FILE>"),
+    ("check", "check"),
+    ("list", "apps list"),
+    ("appKey", "apps delete <APP_KEY>"),
+    ("buildKey", "builds delete <BUILD_KEY>"),
+    ("info", "builds info <BUILD_KEY>"),
+];
+
+async fn run_legacy(matches: &ArgMatches<'_>) -> Result<(), PgyerError> {
+    let actions: Vec<_> = LEGACY_ACTIONS
+        .iter()
+        .filter(|(name, _)| matches.is_present(name))
+        .collect();
+    for (_, command) in &actions {
+        eprintln!(
+            "警告: 不带子命令的参数已废弃, 请改用 pgyer-uploader {}",
+            command
+        );
+    }
+    // -k 可以和其他参数一起使用, 其他的操作一次只能执行一个
+    if actions
+        .iter()
+        .filter(|(name, _)| *name != "api_key")
+        .count()
+        > 1
+    {
+        return Err(PgyerError::Validation(
+            "一次只能执行一个操作, 请使用子命令, -h 获取更多帮助".to_owned(),
+        ));
+    }
+
+    // 和旧版本一样先保存 api_key, 再执行其他操作
+    save_api_key(matches)?;
+
+    if matches.is_present("check") {
+        output::print_json_result(&app::check_proxy().await);
+        return Ok(());
+    }
+
+    let client = new_client(matches)?;
+    if matches.value_of("file").is_some() {
+        upload(&client, matches).await?;
     }
 
     if matches.is_present("list") {
-        app::get_app_list(matches.value_of("page").unwrap_or("1")).await;
+        get_app_list(&client, matches).await?;
     }
 
-    if matches.value_of("appKey").is_some() {
-        app::delete_app(&matches).await;
+    if let Some(app_key) = matches.value_of("appKey") {
+        app::delete_app(&client, app_key, cli::delete_options(matches)).await?;
     }
 
-    if matches.value_of("buildKey").is_some() {
-        app::delete_build(&matches).await;
+    if let Some(build_key) = matches.value_of("buildKey") {
+        app::delete_build(&client, build_key, cli::delete_options(matches)).await?;
     }
 
-    if matches.value_of("info").is_some() {
-        app::print_build_info(&matches.value_of("info").unwrap()).await;
+    if let Some(build_key) = matches.value_of("info") {
+        app::print_build_info(&client, build_key).await?;
     }
+
+    Ok(())
 }
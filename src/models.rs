@@ -0,0 +1,168 @@
+//! 蒲公英接口返回的数据结构
+//!
+//! 蒲公英的接口里数字字段有时会以字符串的形式返回, 因此这里的字段统一使用宽松的解析方式
+
+use serde::{de, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+/// 所有接口统一的返回格式
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ApiEnvelope<T> {
+    pub code: i64,
+    #[serde(default)]
+    pub message: String,
+    /// code 不为 0 时没有数据
+    #[serde(default)]
+    pub data: Option<T>,
+}
+
+impl<T> ApiEnvelope<T> {
+    pub fn is_success(&self) -> bool {
+        self.code == 0
+    }
+}
+
+/// getCOSToken 返回的上传凭证
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CosToken {
+    pub key: String,
+    pub endpoint: String,
+    pub params: CosParams,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct CosParams {
+    pub signature: String,
+    #[serde(rename = "x-cos-security-token")]
+    pub x_cos_security_token: String,
+    #[serde(default)]
+    pub key: String,
+}
+
+/// buildInfo 返回的构建信息
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BuildInfo {
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_key: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_type: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_is_first: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_is_lastest: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_file_key: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_file_name: String,
+    #[serde(deserialize_with = "number_or_string")]
+    pub build_file_size: u64,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_name: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_version: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_version_no: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_build_version: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_identifier: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_icon: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_description: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_update_description: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_shortcut_url: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_created: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_updated: String,
+    #[serde(rename = "buildQRCodeURL", deserialize_with = "string_or_number")]
+    pub build_qr_code_url: String,
+}
+
+/// listMy 返回的一页应用
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppListPage {
+    pub list: Vec<AppSummary>,
+    #[serde(deserialize_with = "number_or_string")]
+    pub count: u64,
+    #[serde(deserialize_with = "number_or_string")]
+    pub page_count: u64,
+}
+
+/// builds 返回的某个应用的一页历史构建
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BuildListPage {
+    pub list: Vec<BuildInfo>,
+    #[serde(deserialize_with = "number_or_string")]
+    pub count: u64,
+    #[serde(deserialize_with = "number_or_string")]
+    pub page_count: u64,
+}
+
+/// 应用列表中的一项, 对应应用最新的一次构建
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AppSummary {
+    #[serde(deserialize_with = "string_or_number")]
+    pub app_key: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_key: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_type: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_name: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_version: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_version_no: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_build_version: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_identifier: String,
+    #[serde(deserialize_with = "number_or_string")]
+    pub build_file_size: u64,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_icon: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_description: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_shortcut_url: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_created: String,
+    #[serde(deserialize_with = "string_or_number")]
+    pub build_updated: String,
+}
+
+fn string_or_number<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(String::new()),
+        Value::String(value) => Ok(value),
+        Value::Number(value) => Ok(value.to_string()),
+        Value::Bool(value) => Ok(value.to_string()),
+        other => Err(de::Error::custom(format!("expected string, got {}", other))),
+    }
+}
+
+fn number_or_string<'de, D>(deserializer: D) -> Result<u64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(0),
+        Value::Number(value) => value
+            .as_u64()
+            .ok_or_else(|| de::Error::custom(format!("expected unsigned number, got {}", value))),
+        Value::String(value) if value.is_empty() => Ok(0),
+        Value::String(value) => value.parse().map_err(de::Error::custom),
+        other => Err(de::Error::custom(format!("expected number, got {}", other))),
+    }
+}
@@ -0,0 +1,321 @@
+//! 上传和删除等操作的参数, 与命令行解析无关, 作为库使用时直接构造
+
+use std::{fmt, path::PathBuf, time::Duration};
+
+use chrono::{Days, Local, NaiveDate};
+
+use crate::error::{PgyerError, Result};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_WAIT_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// 上传安装包的参数, 未设置的项使用蒲公英上的默认值
+#[derive(Debug, Clone)]
+pub struct UploadOptions {
+    pub file: PathBuf,
+    /// 渠道短链接
+    pub channel: Option<String>,
+    /// 更新说明
+    pub description: Option<String>,
+    /// 安装方式, 不设置时使用蒲公英上应用的设置
+    pub install_type: Option<InstallType>,
+    pub install_window: InstallWindow,
+    pub duplicate: DuplicatePolicy,
+    /// 上传后不等待服务端处理完成
+    pub no_wait: bool,
+    /// 轮询构建信息的初始间隔, 每次轮询后逐渐变长
+    pub poll_interval: Duration,
+    /// 等待服务端处理的最长时间
+    pub wait_timeout: Duration,
+}
+
+/// 安装方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallType {
+    Public,
+    /// 需要输入密码才能安装
+    Password(String),
+    /// 只有受邀请的用户可以安装
+    Invite,
+}
+
+impl InstallType {
+    /// 按 1/2/3 或 public/password/invite 解析, 密码安装方式需要密码
+    pub fn parse(value: &str, password: Option<&str>) -> Result<InstallType> {
+        match InstallType::code_of(value)? {
+            "1" => Ok(InstallType::Public),
+            "2" => match password {
+                Some(password) if !password.is_empty() => {
+                    Ok(InstallType::Password(password.to_owned()))
+                }
+                _ => Err(PgyerError::Validation(
+                    "密码安装方式需要传递 password 参数".to_owned(),
+                )),
+            },
+            _ => Ok(InstallType::Invite),
+        }
+    }
+
+    /// 检查安装方式的名称, 不需要密码
+    pub fn check_name(value: &str) -> Result<()> {
+        InstallType::code_of(value).map(|_| ())
+    }
+
+    /// 蒲公英接口中 buildInstallType 的取值
+    pub fn code(&self) -> &'static str {
+        match self {
+            InstallType::Public => "1",
+            InstallType::Password(_) => "2",
+            InstallType::Invite => "3",
+        }
+    }
+
+    pub fn password(&self) -> Option<&str> {
+        match self {
+            InstallType::Password(password) => Some(password),
+            _ => None,
+        }
+    }
+
+    fn code_of(value: &str) -> Result<&'static str> {
+        match value.to_ascii_lowercase().as_str() {
+            "1" | "public" => Ok("1"),
+            "2" | "password" => Ok("2"),
+            "3" | "invite" => Ok("3"),
+            _ => Err(PgyerError::Validation(format!(
+                "安装方式只能是 1=public, 2=password, 3=invite: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// 可以安装的时间段
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InstallWindow {
+    #[default]
+    Forever,
+    /// 开始和结束日期都包含在内
+    Range(NaiveDate, NaiveDate),
+}
+
+impl InstallWindow {
+    /// 开始日期默认为今天, 结束日期可以是相对开始日期的天数或周数, 如 +7d, +2w
+    pub fn parse(start: Option<&str>, end: &str) -> Result<InstallWindow> {
+        let start = match start {
+            Some(start) => parse_date(start)?,
+            None => Local::now().date_naive(),
+        };
+        let end = match parse_relative(end)? {
+            Some(days) => start
+                .checked_add_days(days)
+                .ok_or_else(|| PgyerError::Validation(format!("安装结束日期超出范围: {}", end)))?,
+            None => parse_date(end)?,
+        };
+
+        let window = InstallWindow::Range(start, end);
+        window.check()?;
+        Ok(window)
+    }
+
+    /// 结束日期不能早于今天, 开始日期不能晚于结束日期
+    pub fn check(&self) -> Result<()> {
+        if let InstallWindow::Range(start, end) = self {
+            if *end < Local::now().date_naive() {
+                return Err(PgyerError::Validation(format!(
+                    "安装结束日期 {} 已经过去",
+                    end
+                )));
+            }
+            if start > end {
+                return Err(PgyerError::Validation(format!(
+                    "安装开始日期 {} 晚于结束日期 {}",
+                    start, end
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 蒲公英接口中 buildInstallDate 的取值
+    pub fn code(&self) -> &'static str {
+        match self {
+            InstallWindow::Forever => "2",
+            InstallWindow::Range(..) => "1",
+        }
+    }
+}
+
+impl fmt::Display for InstallWindow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InstallWindow::Forever => write!(f, "长期有效"),
+            InstallWindow::Range(start, end) => write!(f, "{} ~ {}", start, end),
+        }
+    }
+}
+
+// 格式为 yyyy-MM-dd, 不存在的日期 (如 2001-02-30) 会被拒绝
+fn parse_date(value: &str) -> Result<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        PgyerError::Validation(format!(
+            "安装日期不正确, 正确格式 yyyy-MM-dd (2001-02-01) 或 +7d, +2w: {}",
+            value
+        ))
+    })
+}
+
+// +7d 或 +2w, 不是相对日期时返回 None
+fn parse_relative(value: &str) -> Result<Option<Days>> {
+    let Some(relative) = value.strip_prefix('+') else {
+        return Ok(None);
+    };
+    let invalid =
+        || PgyerError::Validation(format!("相对日期不正确, 正确格式 +7d 或 +2w: {}", value));
+    let (number, days_per_unit) = match relative.char_indices().last() {
+        Some((i, 'd')) => (&relative[..i], 1),
+        Some((i, 'w')) => (&relative[..i], 7),
+        _ => return Err(invalid()),
+    };
+    let number: u64 = number.parse().map_err(|_| invalid())?;
+    let days = number.checked_mul(days_per_unit).ok_or_else(invalid)?;
+    Ok(Some(Days::new(days)))
+}
+
+/// 上传前是否检查蒲公英上已有相同版本号和构建号的构建
+///
+/// 蒲公英没有返回文件哈希, 只能用文件大小判断是否为同一个文件
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DuplicatePolicy {
+    /// 不检查
+    #[default]
+    Ignore,
+    /// 版本号, 构建号和文件大小都相同时跳过上传, 只有版本相同时输出警告, 继续上传
+    Skip,
+    /// 已有相同版本的构建时返回错误
+    Fail,
+}
+
+impl UploadOptions {
+    pub fn new(file: impl Into<PathBuf>) -> UploadOptions {
+        UploadOptions {
+            file: file.into(),
+            channel: None,
+            description: None,
+            install_type: None,
+            install_window: InstallWindow::Forever,
+            duplicate: DuplicatePolicy::default(),
+            no_wait: false,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            wait_timeout: DEFAULT_WAIT_TIMEOUT,
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if let Some(InstallType::Password(password)) = &self.install_type {
+            if password.is_empty() {
+                return Err(PgyerError::Validation(
+                    "密码安装方式需要传递 password 参数".to_owned(),
+                ));
+            }
+        }
+        self.install_window.check()?;
+
+        if self.poll_interval.is_zero() || self.wait_timeout.is_zero() {
+            return Err(PgyerError::Validation(
+                "轮询间隔和等待时间必须大于 0".to_owned(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// 删除应用或构建的参数
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeleteOptions {
+    /// 不需要确认, 非交互模式下必须设置
+    pub yes: bool,
+    /// 只显示将要删除的内容
+    pub dry_run: bool,
+}
+
+/// config 子命令的操作
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigCommand {
+    Get { key: String, show_secrets: bool },
+    Set { key: String, value: String },
+    Unset { key: String },
+    List { show_secrets: bool },
+    Path,
+    Profiles,
+    Use { name: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(value: &str) -> NaiveDate {
+        parse_date(value).unwrap()
+    }
+
+    #[test]
+    fn parse_absolute_window() {
+        let window = InstallWindow::parse(Some("2999-01-01"), "2999-02-01").unwrap();
+        assert_eq!(
+            window,
+            InstallWindow::Range(date("2999-01-01"), date("2999-02-01"))
+        );
+        assert_eq!(window.code(), "1");
+    }
+
+    #[test]
+    fn parse_relative_end() {
+        let window = InstallWindow::parse(Some("2999-01-01"), "+7d").unwrap();
+        assert_eq!(
+            window,
+            InstallWindow::Range(date("2999-01-01"), date("2999-01-08"))
+        );
+        let window = InstallWindow::parse(Some("2999-01-01"), "+2w").unwrap();
+        assert_eq!(
+            window,
+            InstallWindow::Range(date("2999-01-01"), date("2999-01-15"))
+        );
+    }
+
+    #[test]
+    fn start_defaults_to_today() {
+        let today = Local::now().date_naive();
+        let window = InstallWindow::parse(None, "+0d").unwrap();
+        assert_eq!(window, InstallWindow::Range(today, today));
+    }
+
+    #[test]
+    fn reject_invalid_window() {
+        // 不存在的日期
+        assert!(InstallWindow::parse(Some("2001-02-30"), "2999-01-01").is_err());
+        assert!(InstallWindow::parse(None, "2999-02-30").is_err());
+        // 开始日期晚于结束日期
+        assert!(InstallWindow::parse(Some("2999-02-01"), "2999-01-01").is_err());
+        // 结束日期已经过去
+        assert!(InstallWindow::parse(Some("2000-01-01"), "2000-01-02").is_err());
+        assert!(InstallWindow::parse(Some("2999-01-01"), "+7x").is_err());
+        assert!(InstallWindow::parse(Some("2999-01-01"), "+d").is_err());
+        assert!(InstallWindow::parse(Some("2999-01-01"), "7d").is_err());
+    }
+
+    #[test]
+    fn parse_relative_days() {
+        assert_eq!(parse_relative("+7d").unwrap(), Some(Days::new(7)));
+        assert_eq!(parse_relative("+3w").unwrap(), Some(Days::new(21)));
+        assert_eq!(parse_relative("2999-01-01").unwrap(), None);
+        assert!(parse_relative("+").is_err());
+        assert!(parse_relative("+-1d").is_err());
+        assert!(parse_relative("+18446744073709551615w").is_err());
+    }
+
+    #[test]
+    fn relative_end_out_of_range() {
+        assert!(InstallWindow::parse(Some("2999-01-01"), "+99999999999d").is_err());
+    }
+}
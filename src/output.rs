@@ -0,0 +1,173 @@
+//! 命令的输出格式
+//!
+//! json 模式下 stdout 只输出一个 JSON 文档, 进度和提示信息全部输出到 stderr
+
+use std::{
+    future::Future,
+    io::{self, IsTerminal, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::{error::PgyerError, progress::UploadProgress};
+
+static JSON: AtomicBool = AtomicBool::new(false);
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+tokio::task_local! {
+    // 批量上传中正在上传的文件名和共用的进度条
+    static BATCH_FILE: (String, Arc<UploadProgress>);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+}
+
+impl OutputFormat {
+    pub fn parse(value: &str) -> Option<OutputFormat> {
+        match value {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+pub fn set_format(format: OutputFormat) {
+    JSON.store(format == OutputFormat::Json, Ordering::Relaxed);
+}
+
+pub fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// 批量上传时不输出每个文件的提示信息, 只显示总进度和汇总
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// 输出提示信息, json 模式下输出到 stderr
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            $crate::output::print_status(&format!($($arg)*));
+        }
+    };
+}
+pub(crate) use status;
+
+/// 输出警告和重试日志, 不受 [`set_quiet`] 影响
+macro_rules! warning {
+    ($($arg:tt)*) => {
+        $crate::output::print_warning(&format!($($arg)*))
+    };
+}
+pub(crate) use warning;
+
+/// 批量上传中单个文件的上传, 其中输出的警告会加上文件名, 并输出在进度条上方
+pub async fn in_batch<F: Future>(
+    file: String,
+    progress: Arc<UploadProgress>,
+    future: F,
+) -> F::Output {
+    BATCH_FILE.scope((file, progress), future).await
+}
+
+pub fn print_warning(line: &str) {
+    let printed =
+        BATCH_FILE.try_with(|(file, progress)| progress.println(&format!("[{}] {}", file, line)));
+    if printed.is_err() {
+        print_status(line);
+    }
+}
+
+/// 输出一行提示信息, 不受 [`set_quiet`] 影响
+pub fn print_status(line: &str) {
+    if is_json() {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+/// 提示信息输出的位置是否为终端
+pub fn status_is_terminal() -> bool {
+    if is_json() {
+        io::stderr().is_terminal()
+    } else {
+        io::stdout().is_terminal()
+    }
+}
+
+/// 输出不换行的提示, 用于等待用户输入
+pub fn prompt(message: &str) -> io::Result<()> {
+    if is_json() {
+        eprint!("{}", message);
+        io::stderr().flush()
+    } else {
+        print!("{}", message);
+        io::stdout().flush()
+    }
+}
+
+/// 输出命令的结果, 两种模式下都是格式化的 JSON
+pub fn print_result<T: Serialize>(value: &T) {
+    match serde_json::to_string_pretty(value) {
+        Ok(formatted) => println!("{}", formatted),
+        Err(err) => eprintln!("{}", err),
+    }
+}
+
+/// 只在 json 模式下输出结果, 文本模式下已经通过提示信息输出过了
+pub fn print_json_result<T: Serialize>(value: &T) {
+    if is_json() {
+        print_result(value);
+    }
+}
+
+/// json 模式下错误的输出格式, code 与进程退出码一致
+pub fn error_document(err: &PgyerError, code: i32) -> Value {
+    let mut error = json!({
+        "code": code,
+        "kind": error_kind(err),
+        "message": err.to_string(),
+    });
+    match err {
+        PgyerError::Api { code, .. } => error["apiCode"] = json!(code),
+        PgyerError::Http { status, .. } => error["status"] = json!(status),
+        PgyerError::Duplicate { build_key } => error["buildKey"] = json!(build_key),
+        PgyerError::Batch { failed, total } => {
+            error["failed"] = json!(failed);
+            error["total"] = json!(total);
+        }
+        _ => {}
+    }
+    json!({ "error": error })
+}
+
+fn error_kind(err: &PgyerError) -> &'static str {
+    match err {
+        PgyerError::Network(_) => "network",
+        PgyerError::Http { .. } => "http",
+        PgyerError::Api { .. } => "api",
+        PgyerError::Validation(_) => "validation",
+        PgyerError::Io(_) => "io",
+        PgyerError::Decode(_) => "decode",
+        PgyerError::Processing(_) => "processing",
+        PgyerError::Timeout(_) => "timeout",
+        PgyerError::Duplicate { .. } => "duplicate",
+        PgyerError::Config(_) => "config",
+        PgyerError::Batch { .. } => "batch",
+    }
+}
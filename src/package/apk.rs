@@ -0,0 +1,61 @@
+use std::{fs::File, path::Path};
+
+use zip::ZipArchive;
+
+use crate::error::Result;
+
+use super::{
+    arsc::ResourceTable,
+    axml::{self, AttrValue, Element},
+    invalid, read_entry, PackageInfo, PackageKind,
+};
+
+const MANIFEST: &str = "AndroidManifest.xml";
+const RESOURCES: &str = "resources.arsc";
+
+pub(super) fn read_apk_info(path: &Path) -> Result<PackageInfo> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let manifest =
+        read_entry(&mut archive, MANIFEST)?.ok_or_else(|| invalid("缺少 AndroidManifest.xml"))?;
+    let elements = axml::parse_elements(&manifest)?;
+
+    let find = |name: &str| elements.iter().find(|element| element.name == name);
+    let root = find("manifest").ok_or_else(|| invalid("缺少 manifest 元素"))?;
+    let uses_sdk = find("uses-sdk");
+    let application = find("application");
+
+    // 只有引用类型的属性才需要解析 resources.arsc
+    let needs_resources = [Some(root), uses_sdk, application]
+        .iter()
+        .flatten()
+        .flat_map(|element| element.attributes.values())
+        .any(|value| matches!(value, AttrValue::Reference(_)));
+    let resources = if needs_resources {
+        read_entry(&mut archive, RESOURCES)?
+            .map(|data| ResourceTable::parse(&data))
+            .transpose()?
+    } else {
+        None
+    };
+
+    let attr = |element: Option<&Element>, name: &str| -> Option<String> {
+        match element?.attributes.get(name)? {
+            AttrValue::String(value) => Some(value.clone()),
+            AttrValue::Reference(id) => resources
+                .as_ref()
+                .and_then(|resources| resources.resolve_string(*id))
+                .or_else(|| Some(format!("@0x{:08x}", id))),
+        }
+    };
+
+    Ok(PackageInfo {
+        kind: PackageKind::Apk,
+        identifier: attr(Some(root), "package").ok_or_else(|| invalid("缺少包名"))?,
+        version_name: attr(Some(root), "versionName").unwrap_or_default(),
+        version_code: attr(Some(root), "versionCode").unwrap_or_default(),
+        display_name: attr(application, "label"),
+        min_os_version: attr(uses_sdk, "minSdkVersion"),
+        target_sdk_version: attr(uses_sdk, "targetSdkVersion"),
+        provisioning: None,
+    })
+}
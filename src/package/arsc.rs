@@ -0,0 +1,206 @@
+//! resources.arsc 的解析, 只用于把清单文件里引用的字符串资源 (如应用名称) 解析出来
+
+use std::collections::HashMap;
+
+use crate::error::Result;
+
+use super::{
+    axml::{Reader, StringPool, TYPE_REFERENCE, TYPE_STRING},
+    invalid,
+};
+
+const RES_TABLE_TYPE: u16 = 0x0002;
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_TABLE_PACKAGE_TYPE: u16 = 0x0200;
+const RES_TABLE_TYPE_TYPE: u16 = 0x0201;
+
+const FLAG_SPARSE: u8 = 0x01;
+const FLAG_OFFSET16: u8 = 0x02;
+const ENTRY_FLAG_COMPLEX: u16 = 0x0001;
+const ENTRY_FLAG_COMPACT: u16 = 0x0008;
+const NO_ENTRY: u32 = 0xFFFF_FFFF;
+
+// 引用可能指向另一个引用, 限制查找深度避免死循环
+const MAX_REFERENCE_DEPTH: usize = 8;
+
+#[derive(Clone, Copy)]
+struct ResValue {
+    data_type: u8,
+    data: u32,
+    /// 是否为默认语言下的值
+    is_default: bool,
+}
+
+pub(crate) struct ResourceTable {
+    strings: StringPool,
+    values: HashMap<u32, ResValue>,
+}
+
+impl ResourceTable {
+    pub(crate) fn parse(data: &[u8]) -> Result<ResourceTable> {
+        let reader = Reader::new(data);
+        if reader.u16(0)? != RES_TABLE_TYPE {
+            return Err(invalid("resources.arsc 格式不正确"));
+        }
+
+        let mut strings = None;
+        let mut values = HashMap::new();
+        let mut offset = reader.u16(2)? as usize;
+        while offset + 8 <= reader.len() {
+            let chunk_type = reader.u16(offset)?;
+            let chunk_size = reader.u32(offset + 4)? as usize;
+            if chunk_size < 8 {
+                return Err(invalid("chunk 长度不正确"));
+            }
+
+            match chunk_type {
+                RES_STRING_POOL_TYPE if strings.is_none() => {
+                    strings = Some(StringPool::parse(&reader, offset)?);
+                }
+                RES_TABLE_PACKAGE_TYPE => parse_package(&reader, offset, chunk_size, &mut values)?,
+                _ => {}
+            }
+            offset += chunk_size;
+        }
+
+        Ok(ResourceTable {
+            strings: strings.ok_or_else(|| invalid("resources.arsc 缺少字符串池"))?,
+            values,
+        })
+    }
+
+    /// 把资源 id 解析为字符串, 找不到时返回空
+    pub(crate) fn resolve_string(&self, id: u32) -> Option<String> {
+        let mut id = id;
+        for _ in 0..MAX_REFERENCE_DEPTH {
+            let value = self.values.get(&id)?;
+            match value.data_type {
+                TYPE_STRING => return self.strings.get(value.data).map(str::to_owned),
+                TYPE_REFERENCE => id = value.data,
+                _ => return None,
+            }
+        }
+        None
+    }
+}
+
+fn parse_package(
+    reader: &Reader,
+    offset: usize,
+    size: usize,
+    values: &mut HashMap<u32, ResValue>,
+) -> Result<()> {
+    let package_id = reader.u32(offset + 8)?;
+    let end = offset + size;
+    let mut chunk = offset + reader.u16(offset + 2)? as usize;
+    while chunk + 8 <= end {
+        let chunk_type = reader.u16(chunk)?;
+        let chunk_size = reader.u32(chunk + 4)? as usize;
+        if chunk_size < 8 {
+            return Err(invalid("chunk 长度不正确"));
+        }
+
+        if chunk_type == RES_TABLE_TYPE_TYPE {
+            parse_type(reader, chunk, package_id, values)?;
+        }
+        chunk += chunk_size;
+    }
+    Ok(())
+}
+
+fn parse_type(
+    reader: &Reader,
+    offset: usize,
+    package_id: u32,
+    values: &mut HashMap<u32, ResValue>,
+) -> Result<()> {
+    let header_size = reader.u16(offset + 2)? as usize;
+    let type_id = reader.u8(offset + 8)? as u32;
+    let flags = reader.u8(offset + 9)?;
+    let entry_count = reader.u32(offset + 12)? as usize;
+    let entries_start = offset + reader.u32(offset + 16)? as usize;
+    // ResTable_config 里 language 字段为 0 表示默认语言
+    let is_default = reader.u16(offset + 20 + 8)? == 0;
+
+    let index_start = offset + header_size;
+    // entry_count 来自文件内容, 不能直接用来申请内存
+    let mut entries = Vec::new();
+    for i in 0..entry_count {
+        let entry = if flags & FLAG_SPARSE != 0 {
+            let index = reader.u16(index_start + i * 4)? as u32;
+            let entry_offset = reader.u16(index_start + i * 4 + 2)? as u32 * 4;
+            (index, entry_offset)
+        } else if flags & FLAG_OFFSET16 != 0 {
+            match reader.u16(index_start + i * 2)? {
+                0xFFFF => continue,
+                entry_offset => (i as u32, entry_offset as u32 * 4),
+            }
+        } else {
+            match reader.u32(index_start + i * 4)? {
+                NO_ENTRY => continue,
+                entry_offset => (i as u32, entry_offset),
+            }
+        };
+        entries.push(entry);
+    }
+
+    for (index, entry_offset) in entries {
+        let entry = entries_start + entry_offset as usize;
+        let entry_flags = reader.u16(entry + 2)?;
+        let value = if entry_flags & ENTRY_FLAG_COMPACT != 0 {
+            ResValue {
+                data_type: (entry_flags >> 8) as u8,
+                data: reader.u32(entry + 4)?,
+                is_default,
+            }
+        } else if entry_flags & ENTRY_FLAG_COMPLEX != 0 {
+            // style/array 等复杂资源用不到
+            continue;
+        } else {
+            let value = entry + reader.u16(entry)? as usize;
+            ResValue {
+                data_type: reader.u8(value + 3)?,
+                data: reader.u32(value + 4)?,
+                is_default,
+            }
+        };
+
+        let id = (package_id << 24) | (type_id << 16) | index;
+        // 优先使用默认语言下的值
+        match values.get(&id) {
+            Some(existing) if existing.is_default || !is_default => {}
+            _ => {
+                values.insert(id, value);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESOURCES: &[u8] = include_bytes!("../../tests/fixtures/resources.arsc");
+
+    #[test]
+    fn resolve_default_locale_string() {
+        let table = ResourceTable::parse(RESOURCES).unwrap();
+        // 同时有 zh 下的值, 应使用默认语言下的值
+        assert_eq!(table.resolve_string(0x7f01_0000).as_deref(), Some("My App"));
+        assert_eq!(table.resolve_string(0x7f01_0001), None);
+        assert_eq!(table.resolve_string(0x7f02_0000), None);
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        assert!(ResourceTable::parse(b"not a resource table").is_err());
+        assert!(ResourceTable::parse(&[]).is_err());
+
+        // chunk 长度小于头部长度
+        let mut data = RESOURCES.to_vec();
+        let offset = u16::from_le_bytes([data[2], data[3]]) as usize;
+        data[offset + 4..offset + 8].copy_from_slice(&4u32.to_le_bytes());
+        assert!(ResourceTable::parse(&data).is_err());
+    }
+}
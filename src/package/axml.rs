@@ -0,0 +1,304 @@
+//! AndroidManifest.xml 等二进制 XML (AXML) 的解析
+
+use std::collections::HashMap;
+
+use crate::error::Result;
+
+use super::invalid;
+
+const RES_STRING_POOL_TYPE: u16 = 0x0001;
+const RES_XML_TYPE: u16 = 0x0003;
+const RES_XML_START_ELEMENT_TYPE: u16 = 0x0102;
+const RES_XML_RESOURCE_MAP_TYPE: u16 = 0x0180;
+
+const UTF8_FLAG: u32 = 1 << 8;
+const NO_INDEX: u32 = 0xFFFF_FFFF;
+
+pub(crate) const TYPE_REFERENCE: u8 = 0x01;
+pub(crate) const TYPE_STRING: u8 = 0x03;
+const TYPE_FLOAT: u8 = 0x04;
+const TYPE_INT_DEC: u8 = 0x10;
+const TYPE_INT_HEX: u8 = 0x11;
+const TYPE_INT_BOOLEAN: u8 = 0x12;
+
+// android 命名空间下常用属性的资源 id, 混淆过的安装包里属性名可能为空, 只能通过资源 id 识别
+const ATTR_RESOURCE_IDS: [(u32, &str); 5] = [
+    (0x0101_0001, "label"),
+    (0x0101_020c, "minSdkVersion"),
+    (0x0101_021b, "versionCode"),
+    (0x0101_021c, "versionName"),
+    (0x0101_0270, "targetSdkVersion"),
+];
+
+/// 按小端序读取二进制数据
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Reader<'a> {
+        Reader { data }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub(crate) fn slice(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        offset
+            .checked_add(len)
+            .and_then(|end| self.data.get(offset..end))
+            .ok_or_else(|| invalid("数据被截断"))
+    }
+
+    pub(crate) fn u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.slice(offset, 1)?[0])
+    }
+
+    pub(crate) fn u16(&self, offset: usize) -> Result<u16> {
+        let bytes = self.slice(offset, 2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&self, offset: usize) -> Result<u32> {
+        let bytes = self.slice(offset, 4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+/// 资源里的字符串池
+pub(crate) struct StringPool {
+    strings: Vec<String>,
+}
+
+impl StringPool {
+    /// `offset` 为字符串池 chunk 的起始位置
+    pub(crate) fn parse(reader: &Reader, offset: usize) -> Result<StringPool> {
+        if reader.u16(offset)? != RES_STRING_POOL_TYPE {
+            return Err(invalid("字符串池格式不正确"));
+        }
+
+        let count = reader.u32(offset + 8)? as usize;
+        let flags = reader.u32(offset + 16)?;
+        let strings_start = offset + reader.u32(offset + 20)? as usize;
+        let header_size = reader.u16(offset + 2)? as usize;
+        let is_utf8 = flags & UTF8_FLAG != 0;
+
+        // count 来自文件内容, 不能直接用来申请内存
+        let mut strings = Vec::new();
+        for i in 0..count {
+            let start = strings_start + reader.u32(offset + header_size + i * 4)? as usize;
+            let value = if is_utf8 {
+                read_utf8(reader, start)?
+            } else {
+                read_utf16(reader, start)?
+            };
+            strings.push(value);
+        }
+
+        Ok(StringPool { strings })
+    }
+
+    pub(crate) fn get(&self, index: u32) -> Option<&str> {
+        if index == NO_INDEX {
+            return None;
+        }
+        self.strings.get(index as usize).map(String::as_str)
+    }
+}
+
+fn read_utf8(reader: &Reader, offset: usize) -> Result<String> {
+    // 先是字符数再是字节数
+    let (_, offset) = read_utf8_len(reader, offset)?;
+    let (byte_len, offset) = read_utf8_len(reader, offset)?;
+    Ok(String::from_utf8_lossy(reader.slice(offset, byte_len)?).into_owned())
+}
+
+// 长度大于 0x7f 时占两个字节, 返回长度和后面数据的位置
+fn read_utf8_len(reader: &Reader, offset: usize) -> Result<(usize, usize)> {
+    let first = reader.u8(offset)? as usize;
+    if first & 0x80 != 0 {
+        let len = ((first & 0x7f) << 8) | reader.u8(offset + 1)? as usize;
+        Ok((len, offset + 2))
+    } else {
+        Ok((first, offset + 1))
+    }
+}
+
+fn read_utf16(reader: &Reader, offset: usize) -> Result<String> {
+    let first = reader.u16(offset)? as usize;
+    let (len, offset) = if first & 0x8000 != 0 {
+        (
+            ((first & 0x7fff) << 16) | reader.u16(offset + 2)? as usize,
+            offset + 4,
+        )
+    } else {
+        (first, offset + 2)
+    };
+
+    let units = (0..len)
+        .map(|i| reader.u16(offset + i * 2))
+        .collect::<Result<Vec<u16>>>()?;
+    Ok(String::from_utf16_lossy(&units))
+}
+
+/// 属性值, 引用类型需要再到 resources.arsc 里查找
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum AttrValue {
+    String(String),
+    Reference(u32),
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Element {
+    pub name: String,
+    pub attributes: HashMap<String, AttrValue>,
+}
+
+/// 解析出所有元素及其属性, 清单文件里只需要少数几个元素, 因此不保留层级
+pub(crate) fn parse_elements(data: &[u8]) -> Result<Vec<Element>> {
+    let reader = Reader::new(data);
+    if reader.u16(0)? != RES_XML_TYPE {
+        return Err(invalid("AndroidManifest.xml 不是二进制 XML"));
+    }
+
+    let mut pool = None;
+    let mut resource_ids: Vec<u32> = Vec::new();
+    let mut elements = Vec::new();
+
+    let mut offset = reader.u16(2)? as usize;
+    while offset + 8 <= reader.len() {
+        let chunk_type = reader.u16(offset)?;
+        let header_size = reader.u16(offset + 2)? as usize;
+        let chunk_size = reader.u32(offset + 4)? as usize;
+        if chunk_size < 8 {
+            return Err(invalid("chunk 长度不正确"));
+        }
+
+        match chunk_type {
+            RES_STRING_POOL_TYPE => pool = Some(StringPool::parse(&reader, offset)?),
+            RES_XML_RESOURCE_MAP_TYPE => {
+                resource_ids = (0..chunk_size.saturating_sub(header_size) / 4)
+                    .map(|i| reader.u32(offset + header_size + i * 4))
+                    .collect::<Result<_>>()?;
+            }
+            RES_XML_START_ELEMENT_TYPE => {
+                let pool = pool.as_ref().ok_or_else(|| invalid("缺少字符串池"))?;
+                elements.push(parse_element(
+                    &reader,
+                    offset,
+                    header_size,
+                    pool,
+                    &resource_ids,
+                )?);
+            }
+            _ => {}
+        }
+
+        offset += chunk_size;
+    }
+
+    Ok(elements)
+}
+
+fn parse_element(
+    reader: &Reader,
+    offset: usize,
+    header_size: usize,
+    pool: &StringPool,
+    resource_ids: &[u32],
+) -> Result<Element> {
+    let ext = offset + header_size;
+    let name = pool
+        .get(reader.u32(ext + 4)?)
+        .unwrap_or_default()
+        .to_owned();
+    let attribute_start = reader.u16(ext + 8)? as usize;
+    let attribute_size = reader.u16(ext + 10)? as usize;
+    let attribute_count = reader.u16(ext + 12)? as usize;
+
+    let mut attributes = HashMap::new();
+    for i in 0..attribute_count {
+        let attr = ext + attribute_start + i * attribute_size;
+        let name_index = reader.u32(attr + 4)?;
+        let raw_value = reader.u32(attr + 8)?;
+        let data_type = reader.u8(attr + 15)?;
+        let data = reader.u32(attr + 16)?;
+
+        let attr_name = resource_ids
+            .get(name_index as usize)
+            .and_then(|id| ATTR_RESOURCE_IDS.iter().find(|(known, _)| known == id))
+            .map(|(_, name)| *name)
+            .or_else(|| pool.get(name_index));
+        let attr_name = match attr_name {
+            Some(attr_name) if !attr_name.is_empty() => attr_name.to_owned(),
+            _ => continue,
+        };
+
+        let value = match (pool.get(raw_value), data_type) {
+            (Some(value), _) => AttrValue::String(value.to_owned()),
+            (None, TYPE_STRING) => AttrValue::String(pool.get(data).unwrap_or_default().to_owned()),
+            (None, TYPE_REFERENCE) => AttrValue::Reference(data),
+            (None, TYPE_INT_DEC) => AttrValue::String((data as i32).to_string()),
+            (None, TYPE_INT_HEX) => AttrValue::String(format!("0x{:08x}", data)),
+            (None, TYPE_INT_BOOLEAN) => AttrValue::String((data != 0).to_string()),
+            (None, TYPE_FLOAT) => AttrValue::String(f32::from_bits(data).to_string()),
+            (None, _) => AttrValue::String(data.to_string()),
+        };
+        attributes.insert(attr_name, value);
+    }
+
+    Ok(Element { name, attributes })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &[u8] = include_bytes!("../../tests/fixtures/AndroidManifest.xml");
+
+    fn find<'a>(elements: &'a [Element], name: &str) -> &'a Element {
+        elements
+            .iter()
+            .find(|element| element.name == name)
+            .unwrap()
+    }
+
+    fn string<'a>(element: &'a Element, name: &str) -> Option<&'a str> {
+        match element.attributes.get(name)? {
+            AttrValue::String(value) => Some(value),
+            AttrValue::Reference(_) => None,
+        }
+    }
+
+    #[test]
+    fn parse_manifest() {
+        let elements = parse_elements(MANIFEST).unwrap();
+        let manifest = find(&elements, "manifest");
+        assert_eq!(string(manifest, "package"), Some("com.example.demo"));
+        assert_eq!(string(manifest, "versionName"), Some("1.2.3"));
+        // 整数类型的属性转换为字符串
+        assert_eq!(string(manifest, "versionCode"), Some("45"));
+
+        let application = find(&elements, "application");
+        assert_eq!(
+            application.attributes.get("label"),
+            Some(&AttrValue::Reference(0x7f01_0000))
+        );
+    }
+
+    #[test]
+    fn reject_invalid_data() {
+        assert!(parse_elements(b"<manifest/>").is_err());
+        assert!(parse_elements(&[]).is_err());
+        assert!(parse_elements(&MANIFEST[..MANIFEST.len() / 2]).is_err());
+    }
+
+    #[test]
+    fn reject_huge_string_count() {
+        // 字符串池中的数量不可信, 不能按它分配内存
+        let mut data = MANIFEST.to_vec();
+        data[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(parse_elements(&data).is_err());
+    }
+}
@@ -0,0 +1,192 @@
+use std::{fs::File, path::Path, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+use zip::ZipArchive;
+
+use crate::error::Result;
+
+use super::{invalid, read_entry, PackageInfo, PackageKind, ProfileKind, ProvisioningProfile};
+
+#[derive(Deserialize)]
+struct InfoPlist {
+    #[serde(rename = "CFBundleIdentifier")]
+    bundle_identifier: String,
+    #[serde(rename = "CFBundleShortVersionString", default)]
+    short_version: String,
+    #[serde(rename = "CFBundleVersion", default)]
+    bundle_version: String,
+    #[serde(rename = "CFBundleDisplayName")]
+    display_name: Option<String>,
+    #[serde(rename = "CFBundleName")]
+    bundle_name: Option<String>,
+    #[serde(rename = "MinimumOSVersion")]
+    minimum_os_version: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MobileProvision {
+    name: Option<String>,
+    team_name: Option<String>,
+    expiration_date: plist::Date,
+    provisioned_devices: Option<Vec<String>>,
+    #[serde(default)]
+    provisions_all_devices: bool,
+    entitlements: Option<Entitlements>,
+}
+
+#[derive(Deserialize)]
+struct Entitlements {
+    #[serde(rename = "get-task-allow", default)]
+    get_task_allow: bool,
+}
+
+pub(super) fn read_ipa_info(path: &Path) -> Result<PackageInfo> {
+    let mut archive = ZipArchive::new(File::open(path)?)?;
+    let app_dir = find_app_dir(&archive).ok_or_else(|| invalid("缺少 Payload/*.app"))?;
+
+    let info_plist = read_entry(&mut archive, &format!("{}Info.plist", app_dir))?
+        .ok_or_else(|| invalid("缺少 Info.plist"))?;
+    // Info.plist 可能是二进制格式也可能是 XML 格式, plist 会自动识别
+    let info: InfoPlist = plist::from_bytes(&info_plist)
+        .map_err(|err| invalid(&format!("Info.plist 格式不正确: {}", err)))?;
+
+    let provisioning = read_entry(
+        &mut archive,
+        &format!("{}embedded.mobileprovision", app_dir),
+    )?
+    .map(|data| parse_provision(&data))
+    .transpose()?;
+
+    Ok(PackageInfo {
+        kind: PackageKind::Ipa,
+        identifier: info.bundle_identifier,
+        version_name: info.short_version,
+        version_code: info.bundle_version,
+        display_name: info.display_name.or(info.bundle_name),
+        min_os_version: info.minimum_os_version,
+        target_sdk_version: None,
+        provisioning,
+    })
+}
+
+/// 返回 `Payload/xxx.app/` 目录
+pub(super) fn find_app_dir(archive: &ZipArchive<File>) -> Option<String> {
+    archive.file_names().find_map(|name| {
+        let rest = name.strip_prefix("Payload/")?;
+        let end = rest.find(".app/")?;
+        if rest[..end].contains('/') {
+            return None;
+        }
+        Some(format!("Payload/{}.app/", &rest[..end]))
+    })
+}
+
+// mobileprovision 是 CMS 签名的数据, 其中内嵌了一段 XML plist
+fn parse_provision(data: &[u8]) -> Result<ProvisioningProfile> {
+    let start = find(data, b"<?xml").ok_or_else(|| invalid("描述文件格式不正确"))?;
+    let end = find(&data[start..], b"</plist>").ok_or_else(|| invalid("描述文件格式不正确"))?
+        + start
+        + "</plist>".len();
+    let provision: MobileProvision = plist::from_bytes(&data[start..end])
+        .map_err(|err| invalid(&format!("描述文件格式不正确: {}", err)))?;
+
+    let get_task_allow = provision
+        .entitlements
+        .map(|entitlements| entitlements.get_task_allow)
+        .unwrap_or_default();
+    let kind = match (
+        &provision.provisioned_devices,
+        provision.provisions_all_devices,
+    ) {
+        (_, true) => ProfileKind::Enterprise,
+        (Some(_), false) if get_task_allow => ProfileKind::Development,
+        (Some(_), false) => ProfileKind::AdHoc,
+        (None, false) => ProfileKind::AppStore,
+    };
+
+    Ok(ProvisioningProfile {
+        kind,
+        name: provision.name.unwrap_or_default(),
+        team_name: provision.team_name.unwrap_or_default(),
+        expiration_date: DateTime::<Local>::from(SystemTime::from(provision.expiration_date)),
+        device_count: provision
+            .provisioned_devices
+            .map_or(0, |devices| devices.len()),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn provision(body: &str) -> Vec<u8> {
+        // 真实的描述文件外面包着一层 CMS 签名
+        let mut data = b"\x30\x82garbage".to_vec();
+        data.extend_from_slice(
+            format!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>Name</key><string>Test</string>
+<key>TeamName</key><string>Team</string>
+<key>ExpirationDate</key><date>2030-01-01T00:00:00Z</date>
+{}
+</dict></plist>"#,
+                body
+            )
+            .as_bytes(),
+        );
+        data.extend_from_slice(b"\xa0\x82signature");
+        data
+    }
+
+    const DEVICES: &str = "<key>ProvisionedDevices</key><array><string>a</string></array>";
+
+    #[test]
+    fn parse_adhoc_fixture() {
+        let profile =
+            parse_provision(include_bytes!("../../tests/fixtures/adhoc.mobileprovision")).unwrap();
+        assert_eq!(profile.kind, ProfileKind::AdHoc);
+        assert_eq!(profile.name, "Demo AdHoc");
+        assert_eq!(profile.team_name, "Example Inc");
+        assert_eq!(profile.device_count, 3);
+        assert!(!profile.is_expired());
+    }
+
+    #[test]
+    fn detect_profile_kind() {
+        let profile = parse_provision(&provision("")).unwrap();
+        assert_eq!(profile.kind, ProfileKind::AppStore);
+        assert_eq!(profile.device_count, 0);
+
+        let profile =
+            parse_provision(&provision("<key>ProvisionsAllDevices</key><true/>")).unwrap();
+        assert_eq!(profile.kind, ProfileKind::Enterprise);
+
+        let profile = parse_provision(&provision(&format!(
+            "{}<key>Entitlements</key><dict><key>get-task-allow</key><true/></dict>",
+            DEVICES
+        )))
+        .unwrap();
+        assert_eq!(profile.kind, ProfileKind::Development);
+
+        let profile = parse_provision(&provision(DEVICES)).unwrap();
+        assert_eq!(profile.kind, ProfileKind::AdHoc);
+        assert_eq!(profile.device_count, 1);
+    }
+
+    #[test]
+    fn reject_invalid_provision() {
+        assert!(parse_provision(b"garbage").is_err());
+        assert!(parse_provision(b"<?xml version=\"1.0\"?><plist>").is_err());
+        // 缺少 ExpirationDate
+        assert!(parse_provision(b"<?xml version=\"1.0\"?><plist><dict/></plist>").is_err());
+    }
+}
@@ -0,0 +1,240 @@
+//! 上传前从本地安装包里读取的应用信息
+
+use std::{fmt, fs::File, io::Read, path::Path};
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+use zip::ZipArchive;
+
+use crate::error::{PgyerError, Result};
+
+mod apk;
+mod arsc;
+mod axml;
+mod ipa;
+mod validate;
+
+pub use validate::validate_package;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageKind {
+    Apk,
+    Ipa,
+}
+
+impl PackageKind {
+    /// 根据文件扩展名判断安装包类型
+    pub fn from_path(path: &Path) -> Option<PackageKind> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "apk" => Some(PackageKind::Apk),
+            "ipa" => Some(PackageKind::Ipa),
+            _ => None,
+        }
+    }
+
+    /// 蒲公英接口里的 buildType
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PackageKind::Apk => "apk",
+            PackageKind::Ipa => "ipa",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageInfo {
+    pub kind: PackageKind,
+    /// android 下为包名, iOS 下为 CFBundleIdentifier
+    pub identifier: String,
+    pub version_name: String,
+    pub version_code: String,
+    /// 应用名称
+    pub display_name: Option<String>,
+    /// 最低支持的系统版本, android 下为 minSdkVersion
+    pub min_os_version: Option<String>,
+    pub target_sdk_version: Option<String>,
+    /// iOS 安装包内嵌的描述文件
+    pub provisioning: Option<ProvisioningProfile>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileKind {
+    Development,
+    AdHoc,
+    Enterprise,
+    AppStore,
+}
+
+impl fmt::Display for ProfileKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ProfileKind::Development => "development",
+            ProfileKind::AdHoc => "ad-hoc",
+            ProfileKind::Enterprise => "enterprise",
+            ProfileKind::AppStore => "app-store",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisioningProfile {
+    pub kind: ProfileKind,
+    pub name: String,
+    pub team_name: String,
+    pub expiration_date: DateTime<Local>,
+    /// 描述文件里登记的设备数, 企业证书不限制设备
+    pub device_count: usize,
+}
+
+impl ProvisioningProfile {
+    pub fn is_expired(&self) -> bool {
+        self.expiration_date <= Local::now()
+    }
+}
+
+impl PackageInfo {
+    /// 读取安装包信息, 类型根据扩展名判断
+    pub fn read(path: &Path) -> Result<PackageInfo> {
+        match PackageKind::from_path(path) {
+            Some(PackageKind::Apk) => apk::read_apk_info(path),
+            Some(PackageKind::Ipa) => ipa::read_ipa_info(path),
+            None => Err(PgyerError::Validation("只支持ipa/apk".to_owned())),
+        }
+    }
+
+    /// 会导致蒲公英上无法安装的问题
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = Vec::new();
+        if let Some(profile) = &self.provisioning {
+            if profile.is_expired() {
+                warnings.push(format!(
+                    "描述文件已于 {} 过期, 上传后将无法安装",
+                    profile.expiration_date.format("%Y-%m-%d %H:%M:%S")
+                ));
+            }
+            if profile.kind == ProfileKind::AppStore {
+                warnings.push("当前为 App Store 描述文件, 上传后将无法安装".to_owned());
+            }
+        }
+        warnings
+    }
+}
+
+impl fmt::Display for PackageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            PackageKind::Apk => writeln!(f, "包名: {}", self.identifier)?,
+            PackageKind::Ipa => writeln!(f, "Bundle ID: {}", self.identifier)?,
+        }
+        write!(f, "版本: {} ({})", self.version_name, self.version_code)?;
+        if let Some(display_name) = &self.display_name {
+            write!(f, "\n应用名称: {}", display_name)?;
+        }
+        if let Some(min_os_version) = &self.min_os_version {
+            match self.kind {
+                PackageKind::Apk => write!(f, "\n最低 SDK: {}", min_os_version)?,
+                PackageKind::Ipa => write!(f, "\n最低系统版本: {}", min_os_version)?,
+            }
+        }
+        if let Some(target_sdk_version) = &self.target_sdk_version {
+            write!(f, "\n目标 SDK: {}", target_sdk_version)?;
+        }
+        if let Some(profile) = &self.provisioning {
+            write!(f, "\n描述文件: {} ({})", profile.name, profile.kind)?;
+            write!(
+                f,
+                "\n描述文件到期时间: {}",
+                profile.expiration_date.format("%Y-%m-%d %H:%M:%S")
+            )?;
+            if profile.kind != ProfileKind::Enterprise {
+                write!(f, "\n设备数: {}", profile.device_count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: &str) -> PgyerError {
+    PgyerError::Validation(format!("解析安装包失败: {}", message))
+}
+
+// 从安装包中读取的单个文件的大小上限
+const MAX_ENTRY_SIZE: u64 = 256 * 1024 * 1024;
+
+fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Option<Vec<u8>>> {
+    let entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+
+    // zip 头中的大小不可信, 按上限读取
+    if entry.size() > MAX_ENTRY_SIZE {
+        return Err(invalid(&format!("{} 过大: {} 字节", name, entry.size())));
+    }
+    let mut data = Vec::with_capacity(entry.size() as usize);
+    entry.take(MAX_ENTRY_SIZE).read_to_end(&mut data)?;
+    Ok(Some(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    #[test]
+    fn read_apk() {
+        let info = PackageInfo::read(&fixture("demo.apk")).unwrap();
+        assert_eq!(info.kind, PackageKind::Apk);
+        assert_eq!(info.identifier, "com.example.demo");
+        assert_eq!(info.version_name, "1.2.3");
+        assert_eq!(info.version_code, "45");
+        assert_eq!(info.display_name.as_deref(), Some("My App"));
+        assert_eq!(info.min_os_version.as_deref(), Some("21"));
+        assert_eq!(info.target_sdk_version.as_deref(), Some("33"));
+        assert!(info.provisioning.is_none());
+        assert!(info.warnings().is_empty());
+    }
+
+    #[test]
+    fn read_ipa() {
+        let info = PackageInfo::read(&fixture("adhoc.ipa")).unwrap();
+        assert_eq!(info.kind, PackageKind::Ipa);
+        assert_eq!(info.identifier, "com.example.ios");
+        assert_eq!(info.version_name, "2.0");
+        assert_eq!(info.version_code, "100");
+        assert_eq!(info.display_name.as_deref(), Some("Demo"));
+        assert_eq!(info.min_os_version.as_deref(), Some("13.0"));
+        let profile = info.provisioning.as_ref().unwrap();
+        assert_eq!(profile.kind, ProfileKind::AdHoc);
+        assert_eq!(profile.device_count, 3);
+        assert!(info.warnings().is_empty());
+    }
+
+    #[test]
+    fn warn_about_uninstallable_profile() {
+        let mut info = PackageInfo::read(&fixture("adhoc.ipa")).unwrap();
+        let profile = info.provisioning.as_mut().unwrap();
+        profile.kind = ProfileKind::AppStore;
+        profile.expiration_date = Local::now() - chrono::Duration::days(1);
+        assert_eq!(info.warnings().len(), 2);
+    }
+
+    #[test]
+    fn reject_unknown_extension() {
+        assert!(PackageInfo::read(Path::new("demo.zip")).is_err());
+    }
+}
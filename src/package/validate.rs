@@ -0,0 +1,171 @@
+use std::{fs::File, path::Path};
+
+use zip::ZipArchive;
+
+use crate::error::{PgyerError, Result};
+
+use super::{ipa::find_app_dir, read_entry, PackageKind};
+
+fn rejected(message: &str) -> PgyerError {
+    PgyerError::Validation(message.to_owned())
+}
+
+/// 检查安装包的内容是否和扩展名一致, 避免上传损坏的文件或者改了扩展名的 aab/xcarchive
+pub fn validate_package(path: &Path) -> Result<PackageKind> {
+    let kind = PackageKind::from_path(path).ok_or_else(|| rejected("只支持ipa/apk"))?;
+    if path.is_dir() {
+        return Err(rejected("安装包不能是目录, xcarchive 请先导出为 ipa"));
+    }
+
+    // zip 的中央目录在文件末尾, 文件不完整时这里就会失败
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(|err| match err {
+        zip::result::ZipError::Io(err) => PgyerError::Io(err),
+        err => rejected(&format!(
+            "安装包不是有效的 zip 文件, 可能已损坏或不完整: {}",
+            err
+        )),
+    })?;
+
+    let has =
+        |archive: &ZipArchive<File>, name: &str| archive.file_names().any(|file| file == name);
+    if has(&archive, "BundleConfig.pb") || has(&archive, "base/manifest/AndroidManifest.xml") {
+        return Err(rejected(
+            "这是一个 Android App Bundle (aab), 蒲公英不支持直接安装, 请先使用 bundletool 生成 apk",
+        ));
+    }
+    if archive
+        .file_names()
+        .any(|file| file.contains("Products/Applications/"))
+    {
+        return Err(rejected(
+            "这是一个 xcarchive, 请先使用 Xcode 或 xcodebuild -exportArchive 导出 ipa",
+        ));
+    }
+
+    match kind {
+        PackageKind::Apk => {
+            for name in ["AndroidManifest.xml", "classes.dex"] {
+                // 读取一遍, 顺便校验 crc
+                if read_entry(&mut archive, name)?.is_none() {
+                    return Err(rejected(&format!(
+                        "apk 中缺少 {}, 请检查安装包是否正确",
+                        name
+                    )));
+                }
+            }
+        }
+        PackageKind::Ipa => {
+            let app_dir = find_app_dir(&archive)
+                .ok_or_else(|| rejected("ipa 中缺少 Payload/*.app, 请检查安装包是否正确"))?;
+            if read_entry(&mut archive, &format!("{}Info.plist", app_dir))?.is_none() {
+                return Err(rejected(&format!(
+                    "ipa 中缺少 {}Info.plist, 请检查安装包是否正确",
+                    app_dir
+                )));
+            }
+        }
+    }
+
+    Ok(kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write, path::PathBuf};
+
+    use tempfile::TempDir;
+    use zip::{write::FileOptions, ZipWriter};
+
+    use super::*;
+
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures")
+            .join(name)
+    }
+
+    fn write_zip(dir: &TempDir, name: &str, entries: &[&str]) -> PathBuf {
+        let path = dir.path().join(name);
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        for entry in entries {
+            zip.start_file(*entry, FileOptions::default()).unwrap();
+            zip.write_all(b"data").unwrap();
+        }
+        zip.finish().unwrap();
+        path
+    }
+
+    fn message(result: Result<PackageKind>) -> String {
+        match result {
+            Err(PgyerError::Validation(message)) => message,
+            other => panic!("expected validation error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn accept_fixtures() {
+        assert_eq!(
+            validate_package(&fixture("demo.apk")).unwrap(),
+            PackageKind::Apk
+        );
+        assert_eq!(
+            validate_package(&fixture("adhoc.ipa")).unwrap(),
+            PackageKind::Ipa
+        );
+    }
+
+    #[test]
+    fn reject_aab() {
+        let dir = TempDir::new().unwrap();
+        let path = write_zip(
+            &dir,
+            "app.apk",
+            &["BundleConfig.pb", "base/manifest/AndroidManifest.xml"],
+        );
+        assert!(message(validate_package(&path)).contains("aab"));
+    }
+
+    #[test]
+    fn reject_xcarchive() {
+        let dir = TempDir::new().unwrap();
+        let path = write_zip(
+            &dir,
+            "app.ipa",
+            &["App.xcarchive/Products/Applications/App.app/Info.plist"],
+        );
+        assert!(message(validate_package(&path)).contains("xcarchive"));
+
+        let path = dir.path().join("App.ipa");
+        fs::create_dir(&path).unwrap();
+        assert!(message(validate_package(&path)).contains("xcarchive"));
+    }
+
+    #[test]
+    fn reject_truncated_zip() {
+        let dir = TempDir::new().unwrap();
+        let data = fs::read(fixture("demo.apk")).unwrap();
+        let path = dir.path().join("demo.apk");
+        fs::write(&path, &data[..data.len() / 2]).unwrap();
+        assert!(message(validate_package(&path)).contains("zip"));
+    }
+
+    #[test]
+    fn reject_missing_entries() {
+        let dir = TempDir::new().unwrap();
+        let path = write_zip(&dir, "app.apk", &["AndroidManifest.xml"]);
+        assert!(message(validate_package(&path)).contains("classes.dex"));
+
+        let path = write_zip(&dir, "app.ipa", &["Info.plist"]);
+        assert!(message(validate_package(&path)).contains("Payload"));
+
+        let path = write_zip(&dir, "app.ipa", &["Payload/App.app/App"]);
+        assert!(message(validate_package(&path)).contains("Info.plist"));
+    }
+
+    #[test]
+    fn reject_unknown_extension() {
+        let dir = TempDir::new().unwrap();
+        let path = write_zip(&dir, "app.zip", &["AndroidManifest.xml", "classes.dex"]);
+        assert!(message(validate_package(&path)).contains("ipa/apk"));
+    }
+}
@@ -0,0 +1,217 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use indicatif::{HumanBytes, HumanDuration, ProgressBar, ProgressDrawTarget, ProgressStyle};
+
+use crate::output::{self, status};
+
+// 非终端环境下输出进度的间隔
+const PLAIN_REPORT_INTERVAL: Duration = Duration::from_secs(5);
+
+fn is_tty() -> bool {
+    output::status_is_terminal()
+}
+
+// 和提示信息输出到同一个位置
+fn draw_target() -> ProgressDrawTarget {
+    if output::is_json() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::stdout()
+    }
+}
+
+/// 上传进度, 终端下显示进度条, 否则定期输出一行文字, 方便 CI 日志查看
+///
+/// 批量上传时所有文件共用一个, 每个文件通过 [`FileProgress`] 记录进度
+pub struct UploadProgress {
+    bar: Option<ProgressBar>,
+    total: u64,
+    sent: AtomicU64,
+    message: Mutex<String>,
+    started: Mutex<Instant>,
+    last_report: Mutex<Instant>,
+}
+
+impl UploadProgress {
+    pub fn new(total: u64) -> UploadProgress {
+        let bar = if is_tty() {
+            let bar = ProgressBar::with_draw_target(total, draw_target());
+            bar.set_style(
+                ProgressStyle::default_bar()
+                    .template("{spinner} [{elapsed_precise}] [{bar:40}] {bytes}/{total_bytes} ({percent}%) {bytes_per_sec}, 剩余 {eta} {msg}")
+                    .progress_chars("=> "),
+            );
+            Some(bar)
+        } else {
+            None
+        };
+
+        let now = Instant::now();
+        UploadProgress {
+            bar,
+            total,
+            sent: AtomicU64::new(0),
+            message: Mutex::new(String::new()),
+            started: Mutex::new(now),
+            last_report: Mutex::new(now),
+        }
+    }
+
+    /// 记录已经被请求体读走的字节数
+    pub fn inc(&self, bytes: u64) {
+        let sent = self.sent.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        if let Some(bar) = &self.bar {
+            bar.inc(bytes);
+            return;
+        }
+
+        let mut last_report = self.last_report.lock().unwrap();
+        if last_report.elapsed() >= PLAIN_REPORT_INTERVAL || sent >= self.total {
+            *last_report = Instant::now();
+            output::print_status(&self.plain_line(sent));
+        }
+    }
+
+    /// 显示在进度后面的文字, 如批量上传已完成的文件数
+    pub fn set_message(&self, message: &str) {
+        *self.message.lock().unwrap() = message.to_owned();
+        if let Some(bar) = &self.bar {
+            bar.set_message(message);
+        }
+    }
+
+    // 重试上传时减去已经计算过的字节数
+    fn rewind(&self, bytes: u64) {
+        self.sent.fetch_sub(bytes, Ordering::Relaxed);
+        if let Some(bar) = &self.bar {
+            bar.set_position(bar.position().saturating_sub(bytes));
+        }
+    }
+
+    /// 在进度条上方输出一行, 不会和进度条混在一起, 进度条和提示信息输出到同一个位置
+    pub fn println(&self, line: &str) {
+        match &self.bar {
+            Some(bar) => bar.println(line),
+            None => output::print_status(line),
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish();
+        }
+    }
+
+    pub fn abandon(&self) {
+        if let Some(bar) = &self.bar {
+            bar.abandon();
+        }
+    }
+
+    fn plain_line(&self, sent: u64) -> String {
+        let elapsed = self.started.lock().unwrap().elapsed().as_secs_f64();
+        let per_sec = if elapsed > 0.0 {
+            (sent as f64 / elapsed) as u64
+        } else {
+            0
+        };
+        let percent = (sent * 100).checked_div(self.total).unwrap_or(100);
+        let eta = Duration::from_secs(
+            self.total
+                .saturating_sub(sent)
+                .checked_div(per_sec)
+                .unwrap_or(0),
+        );
+
+        let line = format!(
+            "已上传 {} / {} ({}%), {}/s, 剩余 {:#}",
+            HumanBytes(sent),
+            HumanBytes(self.total),
+            percent,
+            HumanBytes(per_sec),
+            HumanDuration(eta)
+        );
+        let message = self.message.lock().unwrap();
+        if message.is_empty() {
+            line
+        } else {
+            format!("{}, {}", line, message)
+        }
+    }
+}
+
+/// 单个文件在 [`UploadProgress`] 中的进度
+pub struct FileProgress {
+    total: Arc<UploadProgress>,
+    sent: AtomicU64,
+}
+
+impl FileProgress {
+    pub fn new(total: Arc<UploadProgress>) -> FileProgress {
+        FileProgress {
+            total,
+            sent: AtomicU64::new(0),
+        }
+    }
+
+    pub fn inc(&self, bytes: u64) {
+        self.sent.fetch_add(bytes, Ordering::Relaxed);
+        self.total.inc(bytes);
+    }
+
+    /// 重试上传时从头开始计算
+    pub fn reset(&self) {
+        self.total.rewind(self.sent.swap(0, Ordering::Relaxed));
+    }
+}
+
+/// 等待服务端处理时的转圈提示
+pub struct Spinner {
+    bar: Option<ProgressBar>,
+    message: String,
+    last_report: Instant,
+}
+
+impl Spinner {
+    pub fn new(message: &str) -> Spinner {
+        // 批量上传时只显示总进度
+        let bar = if output::is_quiet() {
+            None
+        } else if is_tty() {
+            let bar = ProgressBar::with_draw_target(!0, draw_target());
+            bar.set_style(ProgressStyle::default_spinner().template("{spinner} {msg} [{elapsed}]"));
+            bar.set_message(message);
+            bar.enable_steady_tick(100);
+            Some(bar)
+        } else {
+            status!("{}", message);
+            None
+        };
+
+        Spinner {
+            bar,
+            message: message.to_owned(),
+            last_report: Instant::now(),
+        }
+    }
+
+    /// 每轮轮询调用一次, 非终端环境下定期输出仍在等待
+    pub fn tick(&mut self) {
+        if self.bar.is_none() && self.last_report.elapsed() >= PLAIN_REPORT_INTERVAL {
+            self.last_report = Instant::now();
+            status!("{}", self.message);
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::{future::Future, time::Duration};
+
+use crate::{
+    error::{PgyerError, Result},
+    output::warning,
+};
+
+/// 可以重试的 HTTP 状态码类别
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatusClass {
+    /// 5xx
+    ServerError,
+    /// 429
+    TooManyRequests,
+    /// 408
+    RequestTimeout,
+}
+
+impl StatusClass {
+    pub fn matches(&self, status: u16) -> bool {
+        match self {
+            StatusClass::ServerError => (500..600).contains(&status),
+            StatusClass::TooManyRequests => status == 429,
+            StatusClass::RequestTimeout => status == 408,
+        }
+    }
+}
+
+/// 网络错误以及指定状态码的重试策略, 每次重试的等待时间按指数增长
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// 总共尝试的次数, 包括第一次请求
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// 在等待时间上增加随机抖动, 避免同时重试
+    pub jitter: bool,
+    pub retry_on: Vec<StatusClass>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            retry_on: vec![
+                StatusClass::ServerError,
+                StatusClass::TooManyRequests,
+                StatusClass::RequestTimeout,
+            ],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// 不重试
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    pub fn is_retryable(&self, err: &PgyerError) -> bool {
+        match err {
+            PgyerError::Network(err) => !err.is_builder() && !err.is_redirect(),
+            PgyerError::Http { status, .. } => {
+                self.retry_on.iter().any(|class| class.matches(*status))
+            }
+            _ => false,
+        }
+    }
+
+    /// 第 `attempt` 次失败后需要等待的时间, `attempt` 从 1 开始
+    pub fn delay(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        if self.jitter {
+            // 在 [delay / 2, delay] 之间随机
+            delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+        } else {
+            delay
+        }
+    }
+
+    /// 执行 `f`, 遇到可重试的错误时等待后重新执行, `what` 用于输出重试日志
+    pub async fn run<T, F, Fut>(&self, what: &str, mut f: F) -> Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt < self.max_attempts && self.is_retryable(&err) => {
+                    let delay = self.delay(attempt);
+                    warning!(
+                        "{} 失败: {}, {:.1} 秒后重试 ({}/{})",
+                        what,
+                        err,
+                        delay.as_secs_f32(),
+                        attempt,
+                        self.max_attempts - 1
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(10),
+            jitter: false,
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn http(status: u16) -> PgyerError {
+        PgyerError::Http {
+            status,
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn delay_grows_exponentially() {
+        let policy = RetryPolicy {
+            jitter: false,
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.delay(1), Duration::from_secs(1));
+        assert_eq!(policy.delay(2), Duration::from_secs(2));
+        assert_eq!(policy.delay(3), Duration::from_secs(4));
+        assert_eq!(policy.delay(6), Duration::from_secs(30));
+        assert_eq!(policy.delay(u32::MAX), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn delay_with_jitter() {
+        let policy = RetryPolicy::default();
+        for attempt in 1..8 {
+            let max = RetryPolicy {
+                jitter: false,
+                ..policy.clone()
+            }
+            .delay(attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay >= max / 2 && delay <= max, "{:?} {:?}", delay, max);
+        }
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&http(500)));
+        assert!(policy.is_retryable(&http(503)));
+        assert!(policy.is_retryable(&http(429)));
+        assert!(policy.is_retryable(&http(408)));
+        assert!(!policy.is_retryable(&http(404)));
+        assert!(!policy.is_retryable(&PgyerError::Validation(String::new())));
+    }
+
+    #[tokio::test]
+    async fn retry_until_max_attempts() {
+        let calls = Cell::new(0);
+        let result: Result<()> = policy()
+            .run("test", || async {
+                calls.set(calls.get() + 1);
+                Err(http(500))
+            })
+            .await;
+        assert!(matches!(result, Err(PgyerError::Http { status: 500, .. })));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn do_not_retry_other_errors() {
+        let calls = Cell::new(0);
+        let result: Result<()> = policy()
+            .run("test", || async {
+                calls.set(calls.get() + 1);
+                Err(PgyerError::Validation("invalid".to_owned()))
+            })
+            .await;
+        assert!(matches!(result, Err(PgyerError::Validation(_))));
+        assert_eq!(calls.get(), 1);
+
+        calls.set(0);
+        let result: Result<()> = RetryPolicy::none()
+            .run("test", || async {
+                calls.set(calls.get() + 1);
+                Err(http(500))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn succeed_after_retry() {
+        let calls = Cell::new(0);
+        let result = policy()
+            .run("test", || async {
+                calls.set(calls.get() + 1);
+                if calls.get() < 3 {
+                    Err(http(503))
+                } else {
+                    Ok(calls.get())
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 3);
+    }
+}
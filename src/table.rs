@@ -0,0 +1,512 @@
+//! 应用和构建列表的表格输出
+
+use std::cmp::Ordering;
+
+use console::{measure_text_width, pad_str, truncate_str, Alignment, Term};
+use indicatif::HumanBytes;
+
+use crate::{
+    app::UploadOutcome,
+    batch::FileResult,
+    error::{PgyerError, Result},
+    models::{AppSummary, BuildInfo},
+};
+
+const PGYER_SHORT_URL: &str = "https://www.pgyer.com/";
+const COLUMN_GAP: &str = "  ";
+// 列宽不足时最多压缩到的宽度
+const MIN_COLUMN_WIDTH: usize = 8;
+
+/// 表格中的一列
+pub struct Column<T> {
+    /// --columns 中使用的名称
+    pub name: &'static str,
+    pub header: &'static str,
+    value: fn(&T) -> String,
+    /// 终端宽度不够时是否可以截断
+    shrinkable: bool,
+}
+
+/// 列表输出的列和排序方式
+pub struct TableOptions<T> {
+    pub columns: Vec<Column<T>>,
+    /// 不设置时使用接口返回的顺序
+    pub sort: Option<SortKey>,
+}
+
+/// 列表的排序方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// 最新上传的在前
+    Date,
+    Name,
+    /// 最大的在前
+    Size,
+}
+
+impl SortKey {
+    pub fn parse(value: &str) -> Result<SortKey> {
+        match value {
+            "date" => Ok(SortKey::Date),
+            "name" => Ok(SortKey::Name),
+            "size" => Ok(SortKey::Size),
+            _ => Err(PgyerError::Validation(format!(
+                "sort 只能是 date, name 或 size: {}",
+                value
+            ))),
+        }
+    }
+}
+
+/// 应用列表可用的列
+pub fn app_columns() -> Vec<Column<AppSummary>> {
+    vec![
+        Column {
+            name: "key",
+            header: "APP KEY",
+            value: |app| app.app_key.clone(),
+            shrinkable: false,
+        },
+        Column {
+            name: "name",
+            header: "名称",
+            value: |app| app.build_name.clone(),
+            shrinkable: true,
+        },
+        Column {
+            name: "identifier",
+            header: "包名",
+            value: |app| app.build_identifier.clone(),
+            shrinkable: true,
+        },
+        Column {
+            name: "type",
+            header: "类型",
+            value: |app| build_type_name(&app.build_type).to_owned(),
+            shrinkable: false,
+        },
+        Column {
+            name: "version",
+            header: "版本",
+            value: |app| app.build_version.clone(),
+            shrinkable: true,
+        },
+        Column {
+            name: "build",
+            header: "构建号",
+            value: |app| app.build_version_no.clone(),
+            shrinkable: false,
+        },
+        Column {
+            name: "size",
+            header: "大小",
+            value: |app| HumanBytes(app.build_file_size).to_string(),
+            shrinkable: false,
+        },
+        Column {
+            name: "date",
+            header: "上传时间",
+            value: |app| app.build_created.clone(),
+            shrinkable: false,
+        },
+        Column {
+            name: "url",
+            header: "短链接",
+            value: |app| short_url(&app.build_shortcut_url),
+            // 截断后的链接没有意义
+            shrinkable: false,
+        },
+    ]
+}
+
+/// 默认显示的列
+pub const DEFAULT_APP_COLUMNS: &str = "name,type,version,build,size,date,url";
+
+/// 构建列表可用的列
+pub fn build_columns() -> Vec<Column<BuildInfo>> {
+    vec![
+        Column {
+            name: "key",
+            header: "BUILD KEY",
+            value: |build| build.build_key.clone(),
+            shrinkable: false,
+        },
+        Column {
+            name: "name",
+            header: "名称",
+            value: |build| build.build_name.clone(),
+            shrinkable: true,
+        },
+        Column {
+            name: "version",
+            header: "版本",
+            value: |build| build.build_version.clone(),
+            shrinkable: true,
+        },
+        Column {
+            name: "build",
+            header: "构建号",
+            value: |build| build.build_version_no.clone(),
+            shrinkable: false,
+        },
+        Column {
+            name: "size",
+            header: "大小",
+            value: |build| HumanBytes(build.build_file_size).to_string(),
+            shrinkable: false,
+        },
+        Column {
+            name: "date",
+            header: "上传时间",
+            value: |build| build.build_created.clone(),
+            shrinkable: false,
+        },
+        Column {
+            name: "description",
+            header: "更新说明",
+            // 多行的更新说明合并为一行
+            value: |build| {
+                build
+                    .build_update_description
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            },
+            shrinkable: true,
+        },
+        Column {
+            name: "url",
+            header: "短链接",
+            value: |build| short_url(&build.build_shortcut_url),
+            shrinkable: false,
+        },
+    ]
+}
+
+/// 默认显示的列
+pub const DEFAULT_BUILD_COLUMNS: &str = "version,build,date,size,description,key";
+
+/// 批量上传的汇总
+pub fn upload_result_columns() -> Vec<Column<FileResult>> {
+    vec![
+        Column {
+            name: "file",
+            header: "文件",
+            value: |result| result.file.display().to_string(),
+            shrinkable: true,
+        },
+        Column {
+            name: "result",
+            header: "结果",
+            value: |result| {
+                match &result.outcome {
+                    Ok(UploadOutcome::Published(_)) => "成功",
+                    Ok(UploadOutcome::Skipped(_)) => "已存在",
+                    Ok(UploadOutcome::Processing { .. }) => "处理中",
+                    Err(_) => "失败",
+                }
+                .to_owned()
+            },
+            shrinkable: false,
+        },
+        Column {
+            name: "version",
+            header: "版本",
+            value: |result| match result_build(result) {
+                Some(build) => format!("{} ({})", build.build_version, build.build_version_no),
+                None => String::new(),
+            },
+            shrinkable: true,
+        },
+        Column {
+            name: "key",
+            header: "BUILD KEY",
+            value: |result| match &result.outcome {
+                Ok(UploadOutcome::Processing { build_key }) => build_key.clone(),
+                _ => result_build(result)
+                    .map(|build| build.build_key.clone())
+                    .unwrap_or_default(),
+            },
+            shrinkable: false,
+        },
+        Column {
+            name: "message",
+            header: "短链接/错误",
+            // 错误信息可能有多行
+            value: |result| match &result.outcome {
+                Err(err) => err
+                    .to_string()
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                _ => result_build(result)
+                    .map(|build| short_url(&build.build_shortcut_url))
+                    .unwrap_or_default(),
+            },
+            shrinkable: true,
+        },
+    ]
+}
+
+/// 可以排序的行
+pub trait Row {
+    fn name(&self) -> &str;
+    fn created(&self) -> &str;
+    fn file_size(&self) -> u64;
+}
+
+impl Row for AppSummary {
+    fn name(&self) -> &str {
+        &self.build_name
+    }
+
+    fn created(&self) -> &str {
+        &self.build_created
+    }
+
+    fn file_size(&self) -> u64 {
+        self.build_file_size
+    }
+}
+
+impl Row for BuildInfo {
+    fn name(&self) -> &str {
+        &self.build_name
+    }
+
+    fn created(&self) -> &str {
+        &self.build_created
+    }
+
+    fn file_size(&self) -> u64 {
+        self.build_file_size
+    }
+}
+
+pub fn sort_rows<T: Row>(rows: &mut [T], key: SortKey) {
+    rows.sort_by(|a, b| match key {
+        SortKey::Date => b.created().cmp(a.created()),
+        SortKey::Name => compare_names(a.name(), b.name()),
+        SortKey::Size => b.file_size().cmp(&a.file_size()),
+    });
+}
+
+/// 按逗号分隔的名称选择列
+pub fn select_columns<T>(columns: Vec<Column<T>>, names: &str) -> Result<Vec<Column<T>>> {
+    let available: Vec<_> = columns.iter().map(|column| column.name).collect();
+    let mut columns: Vec<Option<Column<T>>> = columns.into_iter().map(Some).collect();
+    let mut selected = Vec::new();
+    for name in names
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+    {
+        let index = columns
+            .iter()
+            .position(|column| column.as_ref().is_some_and(|column| column.name == name));
+        match index {
+            Some(index) => selected.extend(columns[index].take()),
+            None => {
+                return Err(PgyerError::Validation(format!(
+                    "未知或重复的列: {}, 可用的列: {}",
+                    name,
+                    available.join(", ")
+                )));
+            }
+        }
+    }
+
+    if selected.is_empty() {
+        return Err(PgyerError::Validation("至少需要选择一列".to_owned()));
+    }
+    Ok(selected)
+}
+
+/// 输出到终端时的可用宽度, 重定向到文件时不限制
+pub fn terminal_width() -> Option<usize> {
+    Term::stdout()
+        .size_checked()
+        .map(|(_, width)| width as usize)
+}
+
+/// 渲染表格, 超过 `max_width` 时依次截断最宽的可截断列
+pub fn render<T>(rows: &[T], columns: &[Column<T>], max_width: Option<usize>) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| columns.iter().map(|column| (column.value)(row)).collect())
+        .collect();
+    let mut widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            cells
+                .iter()
+                .map(|row| measure_text_width(&row[i]))
+                .chain([measure_text_width(column.header)])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    if let Some(max_width) = max_width {
+        let gaps = COLUMN_GAP.len() * columns.len().saturating_sub(1);
+        while widths.iter().sum::<usize>() + gaps > max_width {
+            let widest = widths
+                .iter()
+                .enumerate()
+                .filter(|(i, width)| columns[*i].shrinkable && **width > MIN_COLUMN_WIDTH)
+                .max_by_key(|(_, width)| **width)
+                .map(|(i, _)| i);
+            match widest {
+                Some(i) => widths[i] -= 1,
+                None => break,
+            }
+        }
+    }
+
+    let headers: Vec<String> = columns
+        .iter()
+        .map(|column| column.header.to_owned())
+        .collect();
+    let mut lines = Vec::with_capacity(cells.len() + 1);
+    for row in [headers].iter().chain(&cells) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| {
+                // truncate_str 在宽度正好相等时也会截断
+                let cell = if measure_text_width(cell) > *width {
+                    truncate_str(cell, *width, "…")
+                } else {
+                    cell.into()
+                };
+                pad_str(&cell, *width, Alignment::Left, None).into_owned()
+            })
+            .collect();
+        lines.push(line.join(COLUMN_GAP).trim_end().to_owned());
+    }
+    lines.join("\n")
+}
+
+fn result_build(result: &FileResult) -> Option<&BuildInfo> {
+    match &result.outcome {
+        Ok(UploadOutcome::Published(build)) | Ok(UploadOutcome::Skipped(build)) => Some(build),
+        _ => None,
+    }
+}
+
+fn build_type_name(build_type: &str) -> &str {
+    match build_type {
+        "1" => "iOS",
+        "2" => "Android",
+        _ => build_type,
+    }
+}
+
+fn short_url(shortcut: &str) -> String {
+    if shortcut.is_empty() {
+        return String::new();
+    }
+    format!("{}{}", PGYER_SHORT_URL, shortcut)
+}
+
+fn compare_names(a: &str, b: &str) -> Ordering {
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn app(name: &str, version: &str, created: &str, size: u64) -> AppSummary {
+        AppSummary {
+            app_key: "key".to_owned(),
+            build_name: name.to_owned(),
+            build_type: "2".to_owned(),
+            build_version: version.to_owned(),
+            build_version_no: "1".to_owned(),
+            build_created: created.to_owned(),
+            build_file_size: size,
+            ..AppSummary::default()
+        }
+    }
+
+    fn names<T>(columns: &[Column<T>]) -> Vec<&str> {
+        columns.iter().map(|column| column.name).collect()
+    }
+
+    #[test]
+    fn select_columns_in_order() {
+        let columns = select_columns(app_columns(), "url, name,,type").unwrap();
+        assert_eq!(names(&columns), ["url", "name", "type"]);
+
+        let columns = select_columns(app_columns(), DEFAULT_APP_COLUMNS).unwrap();
+        assert_eq!(columns.len(), 7);
+        assert!(select_columns(build_columns(), DEFAULT_BUILD_COLUMNS).is_ok());
+    }
+
+    #[test]
+    fn reject_invalid_columns() {
+        assert!(select_columns(app_columns(), "name,unknown").is_err());
+        assert!(select_columns(app_columns(), "name,name").is_err());
+        assert!(select_columns(app_columns(), "").is_err());
+        assert!(select_columns(app_columns(), " , ").is_err());
+    }
+
+    #[test]
+    fn render_table() {
+        let rows = [
+            app("Demo", "1.0", "2024-01-02 10:00:00", 1024),
+            app("Longer Name", "1.10", "2024-01-01 10:00:00", 0),
+        ];
+        let columns = select_columns(app_columns(), "name,type,version").unwrap();
+        let table = render(&rows, &columns, None);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        // 名称 占 4 个宽度, 补齐到最长的值
+        assert_eq!(lines[0], "名称         类型     版本");
+        assert_eq!(lines[1], "Demo         Android  1.0");
+        assert_eq!(lines[2], "Longer Name  Android  1.10");
+    }
+
+    #[test]
+    fn truncate_shrinkable_columns() {
+        let rows = [app(
+            "A very long application name",
+            "1.0",
+            "2024-01-01 10:00:00",
+            0,
+        )];
+        let columns = select_columns(app_columns(), "name,date").unwrap();
+        let table = render(&rows, &columns, Some(32));
+        for line in table.lines() {
+            assert!(measure_text_width(line) <= 32, "{}", line);
+        }
+        assert!(table.contains("A very lon…"));
+        // 日期不可截断
+        assert!(table.contains("2024-01-01 10:00:00"));
+
+        // 只能压缩到最小宽度
+        let table = render(&rows, &columns, Some(1));
+        assert!(table.contains("A very …"));
+    }
+
+    #[test]
+    fn sort_apps() {
+        let mut rows = [
+            app("b", "1", "2024-01-01 10:00:00", 1),
+            app("A", "1", "2024-01-03 10:00:00", 3),
+            app("c", "1", "2024-01-02 10:00:00", 2),
+        ];
+        let order = |rows: &[AppSummary]| -> Vec<String> {
+            rows.iter().map(|app| app.build_name.clone()).collect()
+        };
+        sort_rows(&mut rows, SortKey::Name);
+        assert_eq!(order(&rows), ["A", "b", "c"]);
+        sort_rows(&mut rows, SortKey::Date);
+        assert_eq!(order(&rows), ["A", "c", "b"]);
+        sort_rows(&mut rows, SortKey::Size);
+        assert_eq!(order(&rows), ["A", "c", "b"]);
+        assert!(SortKey::parse("unknown").is_err());
+    }
+}
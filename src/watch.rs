@@ -0,0 +1,559 @@
+//! 监控目录, 有新的安装包写完后自动上传
+//!
+//! 已经上传过的文件记录在配置目录下的 `uploaded.toml` 中, 重启后不会重复上传
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use chrono::Local;
+use glob::Pattern;
+use notify::{
+    event::{AccessKind, AccessMode, ModifyKind},
+    EventKind, RecursiveMode, Watcher,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::{
+    app::{self, UploadOutcome},
+    batch::{self, FileResult},
+    client::PgyerClient,
+    config::Config,
+    error::{PgyerError, Result},
+    options::UploadOptions,
+    output::{self, print_result, status, warning},
+    retry::RetryPolicy,
+};
+
+const HISTORY_FILE: &str = "uploaded.toml";
+// 检查文件是否写完的间隔
+const TICK: Duration = Duration::from_millis(500);
+// 写入方关闭文件后等待的时间, 有的工具会多次打开和关闭文件
+const CLOSED_SETTLE: Duration = Duration::from_secs(1);
+// 上传失败后重新上传的次数, 包括第一次上传
+const MAX_UPLOAD_ATTEMPTS: u32 = 5;
+
+/// 监控的目录和文件
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+    pub dir: PathBuf,
+    /// 匹配文件名, 不设置时匹配所有 ipa 和 apk
+    pub pattern: Option<Pattern>,
+    /// 文件大小在这段时间内不再变化时认为已经写完
+    pub settle: Duration,
+    /// 启动时目录中已有的文件只记录不上传
+    pub ignore_existing: bool,
+}
+
+impl WatchOptions {
+    pub fn new(dir: impl Into<PathBuf>) -> WatchOptions {
+        WatchOptions {
+            dir: dir.into(),
+            pattern: None,
+            settle: Duration::from_secs(3),
+            ignore_existing: false,
+        }
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        let file_name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(file_name) => file_name,
+            None => return false,
+        };
+        match &self.pattern {
+            Some(pattern) => pattern.matches(file_name),
+            None => {
+                let file_name = file_name.to_ascii_lowercase();
+                file_name.ends_with(".apk") || file_name.ends_with(".ipa")
+            }
+        }
+    }
+}
+
+/// 已经上传过的文件, 按路径记录上传时的大小和修改时间, 文件被覆盖后会重新上传
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct UploadHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    files: BTreeMap<String, HistoryEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub size: u64,
+    /// 修改时间, unix 时间戳
+    pub modified: u64,
+    pub build_key: String,
+    pub uploaded_at: String,
+}
+
+impl UploadHistory {
+    /// 配置目录下的 uploaded.toml
+    pub fn load() -> Result<UploadHistory> {
+        UploadHistory::load_from(Config::path()?.with_file_name(HISTORY_FILE))
+    }
+
+    pub fn load_from(path: PathBuf) -> Result<UploadHistory> {
+        let mut history = if path.exists() {
+            let content = fs::read_to_string(&path)?;
+            toml::from_str(&content).map_err(|err| {
+                PgyerError::Config(format!("上传记录 {} 格式不正确: {}", path.display(), err))
+            })?
+        } else {
+            UploadHistory::default()
+        };
+        history.path = path;
+        Ok(history)
+    }
+
+    pub fn contains(&self, file: &Path) -> Result<bool> {
+        let (key, size, modified) = file_state(file)?;
+        Ok(self
+            .files
+            .get(&key)
+            .is_some_and(|entry| entry.size == size && entry.modified == modified))
+    }
+
+    /// 记录并立即保存
+    pub fn record(&mut self, file: &Path, build_key: &str) -> Result<()> {
+        let (key, size, modified) = file_state(file)?;
+        self.files.insert(
+            key,
+            HistoryEntry {
+                size,
+                modified,
+                build_key: build_key.to_owned(),
+                uploaded_at: Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            },
+        );
+        self.save()
+    }
+
+    fn save(&self) -> Result<()> {
+        let content = toml::to_string_pretty(self)
+            .map_err(|err| PgyerError::Config(format!("保存上传记录失败: {}", err)))?;
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+// 绝对路径, 大小和修改时间
+fn file_state(file: &Path) -> Result<(String, u64, u64)> {
+    let metadata = fs::metadata(file)?;
+    let modified = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let path = fs::canonicalize(file)?;
+    Ok((path.display().to_string(), metadata.len(), modified))
+}
+
+// 等待写完的文件
+struct PendingFile {
+    size: u64,
+    changed: Instant,
+    /// 写入方已经关闭了文件
+    closed: bool,
+}
+
+// 上传失败后等待重新上传的文件
+struct FailedUpload {
+    attempts: u32,
+    retry_at: Instant,
+}
+
+// 重新上传的等待时间, 请求本身已经按客户端的策略重试过, 这里等待更长的时间
+fn requeue_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: MAX_UPLOAD_ATTEMPTS,
+        base_delay: Duration::from_secs(30),
+        max_delay: Duration::from_secs(600),
+        jitter: false,
+        ..RetryPolicy::default()
+    }
+}
+
+/// 一直监控目录, `upload_options` 为每个文件生成上传参数
+///
+/// 单个文件上传失败时只输出错误, 不会停止监控。网络错误和服务端错误导致的失败会等待一段时间后重新上传,
+/// 其他错误在文件再次写入后才会重新上传。每个文件上传后立即保存记录, 可以随时结束进程
+pub async fn watch<F>(
+    client: &PgyerClient,
+    options: &WatchOptions,
+    history: &mut UploadHistory,
+    upload_options: F,
+) -> Result<()>
+where
+    F: Fn(&Path) -> Result<UploadOptions>,
+{
+    if !options.dir.is_dir() {
+        return Err(PgyerError::Validation(format!(
+            "{} 不是目录",
+            options.dir.display()
+        )));
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        // 接收方退出后不再需要事件
+        let _ = tx.send(event);
+    })?;
+    watcher.watch(&options.dir, RecursiveMode::NonRecursive)?;
+    status!("正在监控 {}, 按 Ctrl-C 退出", options.dir.display());
+
+    let mut pending: HashMap<PathBuf, PendingFile> = HashMap::new();
+    let mut failed: HashMap<PathBuf, FailedUpload> = HashMap::new();
+    let policy = requeue_policy();
+    for entry in fs::read_dir(&options.dir)? {
+        let path = entry?.path();
+        if !path.is_file() || !options.matches(&path) || history.contains(&path)? {
+            continue;
+        }
+        if options.ignore_existing {
+            history.record(&path, "")?;
+            continue;
+        }
+        let size = fs::metadata(&path)?.len();
+        pending.insert(
+            path,
+            PendingFile {
+                size,
+                changed: Instant::now(),
+                closed: false,
+            },
+        );
+    }
+
+    let mut tick = tokio::time::interval(TICK);
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let event = match event {
+                    Some(Ok(event)) => event,
+                    Some(Err(err)) => {
+                        status!("监控目录出错: {}", err);
+                        continue;
+                    }
+                    None => return Ok(()),
+                };
+                let closed = event.kind == EventKind::Access(AccessKind::Close(AccessMode::Write));
+                // 上传时读取文件也会产生事件, 只关心写入
+                let written = closed
+                    || matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_))
+                        && !matches!(event.kind, EventKind::Modify(ModifyKind::Metadata(_)));
+                for path in event.paths {
+                    if !options.matches(&path) {
+                        continue;
+                    }
+                    if matches!(event.kind, EventKind::Remove(_)) {
+                        pending.remove(&path);
+                        failed.remove(&path);
+                        continue;
+                    }
+                    if !written {
+                        continue;
+                    }
+                    // 文件重新写入后从头开始计算重新上传的次数
+                    failed.remove(&path);
+                    let size = match fs::metadata(&path) {
+                        Ok(metadata) if metadata.is_file() => metadata.len(),
+                        _ => continue,
+                    };
+                    let entry = pending.entry(path).or_insert(PendingFile {
+                        size,
+                        changed: Instant::now(),
+                        closed: false,
+                    });
+                    if entry.size != size {
+                        entry.size = size;
+                        entry.changed = Instant::now();
+                    }
+                    entry.closed |= closed;
+                }
+            }
+            _ = tick.tick() => {
+                let mut files = ready_files(&mut pending, options.settle);
+                files.extend(due_retries(&failed));
+                for path in files {
+                    match upload_one(client, history, &path, &upload_options).await {
+                        Ok(()) => {
+                            failed.remove(&path);
+                        }
+                        Err(err) => requeue(&mut failed, path, &err, &policy),
+                    }
+                }
+            }
+        }
+    }
+}
+
+// 大小不再变化或者已经关闭的文件, 从等待列表中移除
+fn ready_files(pending: &mut HashMap<PathBuf, PendingFile>, settle: Duration) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    pending.retain(|path, entry| {
+        let size = match fs::metadata(path) {
+            Ok(metadata) => metadata.len(),
+            // 文件已经被移走
+            Err(_) => return false,
+        };
+        if size != entry.size {
+            entry.size = size;
+            entry.changed = Instant::now();
+            entry.closed = false;
+            return true;
+        }
+        let settle = if entry.closed {
+            settle.min(CLOSED_SETTLE)
+        } else {
+            settle
+        };
+        if size > 0 && entry.changed.elapsed() >= settle {
+            ready.push(path.clone());
+            return false;
+        }
+        true
+    });
+    ready.sort();
+    ready
+}
+
+// 到了重新上传时间的文件
+fn due_retries(failed: &HashMap<PathBuf, FailedUpload>) -> Vec<PathBuf> {
+    let now = Instant::now();
+    let mut due: Vec<PathBuf> = failed
+        .iter()
+        .filter(|(_, entry)| entry.retry_at <= now)
+        .map(|(path, _)| path.clone())
+        .collect();
+    due.sort();
+    due
+}
+
+// 可以重试的错误等待一段时间后重新上传, 超过次数或者不能重试时放弃
+fn requeue(
+    failed: &mut HashMap<PathBuf, FailedUpload>,
+    path: PathBuf,
+    err: &PgyerError,
+    policy: &RetryPolicy,
+) {
+    let attempts = failed.get(&path).map_or(1, |entry| entry.attempts + 1);
+    if !policy.is_retryable(err) {
+        failed.remove(&path);
+        return;
+    }
+    if attempts >= policy.max_attempts {
+        failed.remove(&path);
+        warning!(
+            "{} 已经上传失败 {} 次, 文件再次写入后才会重新上传",
+            path.display(),
+            attempts
+        );
+        return;
+    }
+
+    let delay = policy.delay(attempts);
+    warning!(
+        "{} 将在 {} 秒后重新上传 ({}/{})",
+        path.display(),
+        delay.as_secs(),
+        attempts,
+        policy.max_attempts - 1
+    );
+    failed.insert(
+        path,
+        FailedUpload {
+            attempts,
+            retry_at: Instant::now() + delay,
+        },
+    );
+}
+
+// 上传失败时输出错误并返回, 读写上传记录出错时只输出警告, 不会停止监控
+async fn upload_one<F>(
+    client: &PgyerClient,
+    history: &mut UploadHistory,
+    path: &Path,
+    upload_options: &F,
+) -> Result<()>
+where
+    F: Fn(&Path) -> Result<UploadOptions>,
+{
+    match history.contains(path) {
+        Ok(true) => return Ok(()),
+        Ok(false) => {}
+        // 无法确定是否上传过时仍然上传, 重复上传好过漏掉
+        Err(err) => warning!("读取 {} 的上传记录失败: {}", path.display(), err),
+    }
+
+    status!("发现新的安装包: {}", path.display());
+    let outcome = match upload_options(path) {
+        Ok(options) => app::upload(client, &options).await,
+        Err(err) => Err(err),
+    };
+    if let Ok(outcome) = &outcome {
+        let build_key = match outcome {
+            UploadOutcome::Published(build) | UploadOutcome::Skipped(build) => &build.build_key,
+            UploadOutcome::Processing { build_key } => build_key,
+        };
+        if let Err(err) = history.record(path, build_key) {
+            warning!(
+                "保存 {} 的上传记录失败, 重启后会重新上传: {}",
+                path.display(),
+                err
+            );
+        }
+    }
+
+    let result = FileResult {
+        file: path.to_owned(),
+        outcome,
+    };
+    // json 模式下每个文件输出一行
+    if output::is_json() {
+        println!("{}", batch::result_json(&result));
+    } else {
+        match &result.outcome {
+            Ok(UploadOutcome::Published(build)) | Ok(UploadOutcome::Skipped(build)) => {
+                print_result(build)
+            }
+            // 已经输出过 buildKey
+            Ok(UploadOutcome::Processing { .. }) => {}
+            Err(err) => eprintln!("{} 上传失败: {}", path.display(), err),
+        }
+    }
+    result.outcome.map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    const SETTLE: Duration = Duration::from_secs(5);
+
+    fn pending(size: u64, age: Duration, closed: bool) -> PendingFile {
+        PendingFile {
+            size,
+            changed: Instant::now().checked_sub(age).unwrap(),
+            closed,
+        }
+    }
+
+    fn write(dir: &TempDir, name: &str, size: usize) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::write(&path, vec![0u8; size]).unwrap();
+        path
+    }
+
+    #[test]
+    fn wait_until_size_settles() {
+        let dir = TempDir::new().unwrap();
+        let stable = write(&dir, "stable.apk", 4);
+        let recent = write(&dir, "recent.apk", 4);
+        let growing = write(&dir, "growing.apk", 8);
+        let mut files = HashMap::from([
+            (stable.clone(), pending(4, SETTLE * 2, false)),
+            (recent.clone(), pending(4, Duration::ZERO, false)),
+            (growing.clone(), pending(4, SETTLE * 2, false)),
+        ]);
+
+        assert_eq!(ready_files(&mut files, SETTLE), [stable]);
+        assert_eq!(files.len(), 2);
+        // 大小变化后重新计时
+        let entry = &files[&growing];
+        assert_eq!(entry.size, 8);
+        assert!(entry.changed.elapsed() < SETTLE);
+    }
+
+    #[test]
+    fn closed_files_settle_sooner() {
+        let dir = TempDir::new().unwrap();
+        let closed = write(&dir, "closed.apk", 4);
+        let open = write(&dir, "open.apk", 4);
+        let mut files = HashMap::from([
+            (closed.clone(), pending(4, CLOSED_SETTLE, true)),
+            (open.clone(), pending(4, CLOSED_SETTLE, false)),
+        ]);
+        assert_eq!(ready_files(&mut files, SETTLE), [closed]);
+        assert!(files.contains_key(&open));
+    }
+
+    #[test]
+    fn size_change_clears_closed() {
+        let dir = TempDir::new().unwrap();
+        let path = write(&dir, "app.apk", 8);
+        let mut files = HashMap::from([(path.clone(), pending(4, SETTLE * 2, true))]);
+        assert!(ready_files(&mut files, SETTLE).is_empty());
+        assert!(!files[&path].closed);
+    }
+
+    fn server_error() -> PgyerError {
+        PgyerError::Http {
+            status: 503,
+            body: String::new(),
+        }
+    }
+
+    #[test]
+    fn requeue_with_backoff() {
+        let policy = requeue_policy();
+        let path = PathBuf::from("app.apk");
+        let mut failed = HashMap::new();
+        requeue(&mut failed, path.clone(), &server_error(), &policy);
+        let first = failed[&path].retry_at;
+        assert_eq!(failed[&path].attempts, 1);
+        assert!(due_retries(&failed).is_empty());
+
+        requeue(&mut failed, path.clone(), &server_error(), &policy);
+        assert_eq!(failed[&path].attempts, 2);
+        assert!(failed[&path].retry_at > first + policy.base_delay / 2);
+
+        failed.get_mut(&path).unwrap().retry_at = Instant::now();
+        assert_eq!(due_retries(&failed), [path]);
+    }
+
+    #[test]
+    fn give_up_requeue() {
+        let policy = requeue_policy();
+        let path = PathBuf::from("app.apk");
+        let mut failed = HashMap::new();
+        requeue(
+            &mut failed,
+            path.clone(),
+            &PgyerError::Validation("invalid".to_owned()),
+            &policy,
+        );
+        assert!(failed.is_empty());
+
+        for _ in 1..policy.max_attempts {
+            requeue(&mut failed, path.clone(), &server_error(), &policy);
+        }
+        assert_eq!(failed[&path].attempts, policy.max_attempts - 1);
+        requeue(&mut failed, path.clone(), &server_error(), &policy);
+        assert!(failed.is_empty());
+    }
+
+    #[test]
+    fn skip_empty_and_missing_files() {
+        let dir = TempDir::new().unwrap();
+        let empty = write(&dir, "empty.apk", 0);
+        let missing = dir.path().join("missing.apk");
+        let mut files = HashMap::from([
+            (empty.clone(), pending(0, SETTLE * 2, true)),
+            (missing, pending(4, SETTLE * 2, false)),
+        ]);
+        assert!(ready_files(&mut files, SETTLE).is_empty());
+        // 移走的文件不再等待
+        assert_eq!(files.keys().collect::<Vec<_>>(), [&empty]);
+    }
+}
@@ -0,0 +1,279 @@
+//! 使用本地的 mock 服务测试接口请求, 分页和重试
+
+use std::time::Duration;
+
+use futures_util::TryStreamExt;
+use pgyer_uploader::{client::PgyerClient, error::PgyerError, retry::RetryPolicy};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+
+fn client(server: &MockServer) -> PgyerClient {
+    PgyerClient::builder(API_KEY)
+        .base_url(&server.uri())
+        .retry(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            jitter: false,
+            ..RetryPolicy::default()
+        })
+        .build()
+        .unwrap()
+}
+
+// multipart 表单中的一个字段
+fn field(name: &str, value: &str) -> String {
+    format!("name=\"{}\"\r\n\r\n{}\r\n", name, value)
+}
+
+fn success(data: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "code": 0, "message": "", "data": data }))
+}
+
+fn page(list: Vec<Value>, page_count: u64) -> ResponseTemplate {
+    success(json!({ "list": list, "count": 5, "pageCount": page_count }))
+}
+
+fn apps(keys: &[&str]) -> Vec<Value> {
+    keys.iter()
+        .map(|key| json!({ "appKey": key, "buildName": key, "buildType": 2 }))
+        .collect()
+}
+
+async fn mock_page(server: &MockServer, action: &str, page_no: u32, response: ResponseTemplate) {
+    Mock::given(method("POST"))
+        .and(path(format!("/{}", action)))
+        .and(body_string_contains(field("_api_key", API_KEY)))
+        .and(body_string_contains(field("page", &page_no.to_string())))
+        .respond_with(response)
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+#[tokio::test]
+async fn send_api_key_and_params() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/getCOSToken"))
+        .and(body_string_contains(field("_api_key", API_KEY)))
+        .and(body_string_contains(field("buildType", "apk")))
+        .and(body_string_contains(field("buildChannelShortcut", "beta")))
+        .respond_with(success(json!({
+            "key": "build-key",
+            "endpoint": "https://cos.example.com",
+            "params": {
+                "key": "build-key",
+                "signature": "signature",
+                "x-cos-security-token": "token",
+            },
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client(&server)
+        .get_cos_token("apk", &[("buildChannelShortcut", "beta")])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn append_slash_to_base_url() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/apiv2/app/buildInfo"))
+        .and(body_string_contains(field("buildKey", "build-key")))
+        .respond_with(success(json!({ "buildKey": "build-key" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = PgyerClient::builder(API_KEY)
+        .base_url(&format!("{}/apiv2/app", server.uri()))
+        .build()
+        .unwrap();
+    client.build_info("build-key").await.unwrap();
+}
+
+#[tokio::test]
+async fn api_error_code() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/deleteApp"))
+        .and(body_string_contains(field("appKey", "app")))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "code": 1001, "message": "_api_key not found" })),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    assert!(matches!(
+        client(&server).delete_app("app").await,
+        Err(PgyerError::Api { code: 1001, .. })
+    ));
+}
+
+#[tokio::test]
+async fn http_error_status() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/buildDelete"))
+        .respond_with(ResponseTemplate::new(404).set_body_string("not found"))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    match client(&server).delete_build("build").await {
+        Err(PgyerError::Http { status, body }) => {
+            assert_eq!(status, 404);
+            assert_eq!(body, "not found");
+        }
+        other => panic!("expected http error: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn retry_server_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/buildInfo"))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/buildInfo"))
+        .respond_with(success(json!({ "buildKey": "build-key" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    client(&server).build_info("build-key").await.unwrap();
+}
+
+#[tokio::test]
+async fn give_up_after_max_attempts() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/listMy"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&server)
+        .await;
+
+    assert!(matches!(
+        client(&server).list_my(1).await,
+        Err(PgyerError::Http { status: 500, .. })
+    ));
+}
+
+#[tokio::test]
+async fn apps_requests_every_page() {
+    let server = MockServer::start().await;
+    mock_page(&server, "listMy", 1, page(apps(&["a", "b"]), 3)).await;
+    mock_page(&server, "listMy", 2, page(apps(&["c", "d"]), 3)).await;
+    mock_page(&server, "listMy", 3, page(apps(&["e"]), 3)).await;
+
+    let keys: Vec<String> = client(&server)
+        .apps()
+        .map_ok(|app| app.app_key)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(keys, ["a", "b", "c", "d", "e"]);
+}
+
+#[tokio::test]
+async fn apps_stops_at_empty_page() {
+    let server = MockServer::start().await;
+    // pageCount 不准确时, 遇到空页也结束
+    mock_page(&server, "listMy", 1, page(apps(&["a"]), 10)).await;
+    mock_page(&server, "listMy", 2, page(Vec::new(), 10)).await;
+
+    let apps: Vec<_> = client(&server).apps().try_collect().await.unwrap();
+    assert_eq!(apps.len(), 1);
+}
+
+#[tokio::test]
+async fn api_error_stops_stream() {
+    let server = MockServer::start().await;
+    mock_page(
+        &server,
+        "listMy",
+        1,
+        ResponseTemplate::new(200).set_body_json(json!({
+            "code": 1001,
+            "message": "_api_key not found",
+        })),
+    )
+    .await;
+
+    let result: Result<Vec<_>, _> = client(&server).apps().try_collect().await;
+    assert!(matches!(result, Err(PgyerError::Api { code: 1001, .. })));
+}
+
+#[tokio::test]
+async fn app_builds_requests_every_page() {
+    let server = MockServer::start().await;
+    let builds = |keys: &[&str]| -> Vec<Value> {
+        keys.iter()
+            .map(|key| json!({ "buildKey": key, "buildVersion": "1.0" }))
+            .collect()
+    };
+    for (page_no, keys) in [(1, &["a", "b"][..]), (2, &["c"][..])] {
+        Mock::given(method("POST"))
+            .and(path("/builds"))
+            .and(body_string_contains(field("appKey", "app")))
+            .and(body_string_contains(field("page", &page_no.to_string())))
+            .respond_with(page(builds(keys), 2))
+            .expect(1)
+            .mount(&server)
+            .await;
+    }
+
+    let client = client(&server);
+    let keys: Vec<String> = client
+        .app_builds("app")
+        .map_ok(|build| build.build_key)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(keys, ["a", "b", "c"]);
+}
+
+#[tokio::test]
+async fn do_not_retry_delete() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/deleteApp"))
+        .and(body_string_contains(field("appKey", "app")))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/buildDelete"))
+        .and(body_string_contains(field("buildKey", "build")))
+        .respond_with(ResponseTemplate::new(502))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let client = client(&server);
+    assert!(matches!(
+        client.delete_app("app").await,
+        Err(PgyerError::Http { status: 500, .. })
+    ));
+    assert!(matches!(
+        client.delete_build("build").await,
+        Err(PgyerError::Http { status: 502, .. })
+    ));
+}
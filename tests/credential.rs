@@ -0,0 +1,60 @@
+//! 注册自定义的存储后端, 会修改全局状态, 所以放在单独的测试中
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use pgyer_uploader::{
+    config::Config,
+    credential::{self, CredentialStore},
+    error::Result,
+};
+
+#[derive(Default)]
+struct MemoryStore {
+    keys: Mutex<BTreeMap<String, String>>,
+}
+
+impl CredentialStore for MemoryStore {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn get(&self, profile: &str) -> Result<Option<String>> {
+        Ok(self.keys.lock().unwrap().get(profile).cloned())
+    }
+
+    fn set(&self, profile: &str, api_key: &str) -> Result<()> {
+        self.keys
+            .lock()
+            .unwrap()
+            .insert(profile.to_owned(), api_key.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, profile: &str) -> Result<bool> {
+        Ok(self.keys.lock().unwrap().remove(profile).is_some())
+    }
+}
+
+#[test]
+fn use_registered_store() {
+    let store = Arc::new(MemoryStore::default());
+    store.set("default", "registered").unwrap();
+    credential::set_store(store.clone());
+
+    // 配置为明文保存时也使用注册的后端
+    let mut config = Config::default();
+    config.set_option("credential_store", "plaintext").unwrap();
+    assert!(!credential::is_plaintext(&config));
+
+    let registered = credential::from_config(&config).unwrap();
+    assert_eq!(registered.name(), "memory");
+    assert_eq!(
+        registered.get("default").unwrap().as_deref(),
+        Some("registered")
+    );
+    registered.set("beta", "beta-key").unwrap();
+    assert_eq!(store.get("beta").unwrap().as_deref(), Some("beta-key"));
+}
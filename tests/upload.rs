@@ -0,0 +1,351 @@
+//! 使用本地的 mock 服务测试完整的上传流程: getCOSToken, 上传到 cos, 轮询 buildInfo
+
+use std::{path::Path, time::Duration};
+
+use pgyer_uploader::{
+    app::{upload, UploadOutcome},
+    client::PgyerClient,
+    error::PgyerError,
+    options::{DuplicatePolicy, UploadOptions},
+    retry::RetryPolicy,
+};
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{body_string_contains, method, path},
+    Match, Mock, MockBuilder, MockServer, Request, ResponseTemplate,
+};
+
+const API_KEY: &str = "0123456789abcdef0123456789abcdef";
+const BUILD_KEY: &str = "build-key";
+
+fn client(server: &MockServer) -> PgyerClient {
+    PgyerClient::builder(API_KEY)
+        .base_url(&server.uri())
+        .retry(RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(1),
+            jitter: false,
+            ..RetryPolicy::default()
+        })
+        .build()
+        .unwrap()
+}
+
+fn options() -> UploadOptions {
+    let mut options =
+        UploadOptions::new(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/demo.apk"));
+    options.channel = Some("beta".to_owned());
+    options.poll_interval = Duration::from_millis(10);
+    options
+}
+
+// multipart 表单中的一个字段
+fn field(name: &str, value: &str) -> String {
+    format!("name=\"{}\"\r\n\r\n{}\r\n", name, value)
+}
+
+// 上传到 cos 的请求体里有安装包的内容, 不是有效的 utf-8, 不能使用 body_string_contains
+struct BodyContains(String);
+
+impl Match for BodyContains {
+    fn matches(&self, request: &Request) -> bool {
+        let needle = self.0.as_bytes();
+        request
+            .body
+            .windows(needle.len())
+            .any(|window| window == needle)
+    }
+}
+
+fn api_response(code: i64, data: Value) -> ResponseTemplate {
+    ResponseTemplate::new(200).set_body_json(json!({ "code": code, "message": "", "data": data }))
+}
+
+// getCOSToken 和 cos 的连通性检查, cos 的地址指向 mock 服务
+async fn mock_token(server: &MockServer) {
+    Mock::given(method("POST"))
+        .and(path("/getCOSToken"))
+        .and(body_string_contains(field("_api_key", API_KEY)))
+        .and(body_string_contains(field("buildType", "apk")))
+        .and(body_string_contains(field("buildChannelShortcut", "beta")))
+        .respond_with(api_response(
+            0,
+            json!({
+                "key": BUILD_KEY,
+                "endpoint": format!("{}/cos", server.uri()),
+                "params": {
+                    "key": BUILD_KEY,
+                    "signature": "signature",
+                    "x-cos-security-token": "token",
+                },
+            }),
+        ))
+        .expect(1)
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/cos"))
+        .respond_with(ResponseTemplate::new(403))
+        .mount(server)
+        .await;
+}
+
+fn cos_upload() -> MockBuilder {
+    Mock::given(method("POST"))
+        .and(path("/cos"))
+        .and(BodyContains(field("key", BUILD_KEY)))
+        .and(BodyContains(field("signature", "signature")))
+        .and(BodyContains(field("x-cos-security-token", "token")))
+        .and(BodyContains(
+            "name=\"file\"; filename=\"demo.apk\"".to_owned(),
+        ))
+}
+
+fn build_info() -> MockBuilder {
+    Mock::given(method("POST"))
+        .and(path("/buildInfo"))
+        .and(body_string_contains(field("buildKey", BUILD_KEY)))
+}
+
+fn published() -> ResponseTemplate {
+    api_response(
+        0,
+        json!({ "buildKey": BUILD_KEY, "buildVersion": "1.2.3", "buildVersionNo": "45" }),
+    )
+}
+
+#[tokio::test]
+async fn upload_and_wait_for_processing() {
+    let server = MockServer::start().await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    // 第一次查询时服务端还在处理
+    build_info()
+        .respond_with(api_response(1247, Value::Null))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    build_info()
+        .respond_with(published())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    match upload(&client(&server), &options()).await.unwrap() {
+        UploadOutcome::Published(build) => {
+            assert_eq!(build.build_key, BUILD_KEY);
+            assert_eq!(build.build_version, "1.2.3");
+            assert_eq!(build.build_version_no, "45");
+        }
+        other => panic!("expected published build: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn retry_failed_cos_upload() {
+    let server = MockServer::start().await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&server)
+        .await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    build_info()
+        .respond_with(published())
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let outcome = upload(&client(&server), &options()).await.unwrap();
+    assert!(matches!(outcome, UploadOutcome::Published(_)));
+}
+
+#[tokio::test]
+async fn processing_failed() {
+    let server = MockServer::start().await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+    build_info()
+        .respond_with(api_response(1216, Value::Null))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let err = upload(&client(&server), &options()).await.unwrap_err();
+    assert!(matches!(err, PgyerError::Processing(_)));
+}
+
+#[tokio::test]
+async fn reject_invalid_package_before_requesting_token() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let dir = tempfile::TempDir::new().unwrap();
+    let file = dir.path().join("broken.apk");
+    std::fs::write(&file, b"not a zip").unwrap();
+    let err = upload(&client(&server), &UploadOptions::new(file))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, PgyerError::Validation(_)));
+}
+
+#[tokio::test]
+async fn wait_timeout() {
+    let server = MockServer::start().await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+    build_info()
+        .respond_with(api_response(1247, Value::Null))
+        .mount(&server)
+        .await;
+
+    let mut options = options();
+    options.wait_timeout = Duration::from_millis(100);
+    let err = upload(&client(&server), &options).await.unwrap_err();
+    assert!(matches!(err, PgyerError::Timeout(_)));
+    assert_eq!(err.exit_code(), 9);
+}
+
+#[tokio::test]
+async fn wait_timeout_while_polling() {
+    let server = MockServer::start().await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .mount(&server)
+        .await;
+    // buildInfo 请求本身没有响应, 也要在等待时间内结束
+    build_info()
+        .respond_with(published().set_delay(Duration::from_secs(30)))
+        .mount(&server)
+        .await;
+
+    let mut options = options();
+    options.wait_timeout = Duration::from_millis(100);
+    let start = std::time::Instant::now();
+    let err = upload(&client(&server), &options).await.unwrap_err();
+    assert!(matches!(err, PgyerError::Timeout(_)));
+    assert!(start.elapsed() < Duration::from_secs(10));
+}
+
+#[tokio::test]
+async fn no_wait() {
+    let server = MockServer::start().await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    build_info()
+        .respond_with(published())
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let mut options = options();
+    options.no_wait = true;
+    match upload(&client(&server), &options).await.unwrap() {
+        UploadOutcome::Processing { build_key } => assert_eq!(build_key, BUILD_KEY),
+        other => panic!("expected processing build: {:?}", other),
+    }
+}
+
+// 蒲公英上 demo.apk 对应的应用, 以及一个相同版本号和构建号的构建
+async fn mock_existing_build(server: &MockServer, file_size: u64) {
+    let page = |list: Value| api_response(0, json!({ "list": list, "count": 1, "pageCount": 1 }));
+    Mock::given(method("POST"))
+        .and(path("/listMy"))
+        .respond_with(page(json!([{
+            "appKey": "app",
+            "buildIdentifier": "com.example.demo",
+            "buildType": 2,
+        }])))
+        .mount(server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/builds"))
+        .and(body_string_contains(field("appKey", "app")))
+        .respond_with(page(json!([{
+            "buildKey": "existing",
+            "buildVersion": "1.2.3",
+            "buildVersionNo": "45",
+            "buildFileSize": file_size,
+        }])))
+        .mount(server)
+        .await;
+}
+
+fn fixture_size() -> u64 {
+    std::fs::metadata(&options().file).unwrap().len()
+}
+
+#[tokio::test]
+async fn skip_identical_build() {
+    let server = MockServer::start().await;
+    mock_existing_build(&server, fixture_size()).await;
+    Mock::given(method("POST"))
+        .and(path("/getCOSToken"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(0)
+        .mount(&server)
+        .await;
+
+    let mut options = options();
+    options.duplicate = DuplicatePolicy::Skip;
+    match upload(&client(&server), &options).await.unwrap() {
+        UploadOutcome::Skipped(build) => assert_eq!(build.build_key, "existing"),
+        other => panic!("expected skipped build: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn upload_when_only_version_matches() {
+    let server = MockServer::start().await;
+    mock_existing_build(&server, fixture_size() + 1).await;
+    mock_token(&server).await;
+    cos_upload()
+        .respond_with(ResponseTemplate::new(204))
+        .expect(1)
+        .mount(&server)
+        .await;
+    build_info().respond_with(published()).mount(&server).await;
+
+    let mut options = options();
+    options.duplicate = DuplicatePolicy::Skip;
+    let outcome = upload(&client(&server), &options).await.unwrap();
+    assert!(matches!(outcome, UploadOutcome::Published(_)));
+}
+
+#[tokio::test]
+async fn fail_on_duplicate_version() {
+    let server = MockServer::start().await;
+    mock_existing_build(&server, fixture_size() + 1).await;
+
+    let mut options = options();
+    options.duplicate = DuplicatePolicy::Fail;
+    let err = upload(&client(&server), &options).await.unwrap_err();
+    assert!(matches!(err, PgyerError::Duplicate { .. }));
+    assert_eq!(err.exit_code(), 10);
+}